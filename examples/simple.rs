@@ -7,6 +7,7 @@ use bifrost_ecs::core::{
 
 struct Name(String);
 
+#[allow(dead_code)]
 struct Position(f32, f32);
 
 pub struct Explosion {}
@@ -24,8 +25,7 @@ pub struct CollisionEvent {
 }
 
 fn query(scene: &mut scene::Scene) {
-    let query = scene.query::<(&Name, &Position)>();
-    for (name, position) in query.iter() {
+    for (name, position) in scene.query::<(&Name, &Position)>() {
         println!("Name: {:?}, Position: {:?}", name.0, position.0);
     }
 
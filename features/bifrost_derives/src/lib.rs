@@ -23,3 +23,223 @@ fn impl_event_macro(ast: &syn::DeriveInput) -> TokenStream {
 
     gen.into()
 }
+
+// #[component(storage = "sparse", on_add = hook, on_insert = hook, on_remove = hook,
+// require(Velocity, Mass = || Mass(1.0)))], required components without a constructor
+// are built with their `Default` impl
+#[proc_macro_derive(Component, attributes(component))]
+pub fn component_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+    impl_component_macro(&ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct ComponentAttributes {
+    storage: Option<proc_macro2::TokenStream>,
+    on_add: Option<syn::Expr>,
+    on_insert: Option<syn::Expr>,
+    on_remove: Option<syn::Expr>,
+    required: Vec<(syn::Path, Option<syn::Expr>)>,
+}
+
+fn parse_component_attributes(ast: &syn::DeriveInput) -> syn::Result<ComponentAttributes> {
+    let mut attributes = ComponentAttributes::default();
+    for attr in ast.attrs.iter().filter(|attr| attr.path().is_ident("component")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("storage") {
+                let storage: syn::LitStr = meta.value()?.parse()?;
+                attributes.storage = Some(match storage.value().as_str() {
+                    "table" => quote! { bifrost_ecs::core::component::StorageType::Table },
+                    "sparse" => quote! { bifrost_ecs::core::component::StorageType::SparseSet },
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            storage,
+                            "expected \"table\" or \"sparse\"",
+                        ))
+                    }
+                });
+            } else if meta.path.is_ident("on_add") {
+                attributes.on_add = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("on_insert") {
+                attributes.on_insert = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("on_remove") {
+                attributes.on_remove = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("require") {
+                meta.parse_nested_meta(|required| {
+                    let constructor = if required.input.peek(syn::Token![=]) {
+                        Some(required.value()?.parse()?)
+                    } else {
+                        None
+                    };
+                    attributes.required.push((required.path, constructor));
+                    Ok(())
+                })?;
+            } else {
+                return Err(meta.error("unsupported component attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attributes)
+}
+
+fn impl_component_macro(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let attributes = parse_component_attributes(ast)?;
+
+    let storage = attributes.storage.map(|storage| {
+        quote! {
+            const STORAGE_TYPE: bifrost_ecs::core::component::StorageType = #storage;
+        }
+    });
+
+    let on_add = attributes.on_add.map(|hook| quote! { hooks.on_add(#hook); });
+    let on_insert = attributes.on_insert.map(|hook| quote! { hooks.on_insert(#hook); });
+    let on_remove = attributes.on_remove.map(|hook| quote! { hooks.on_remove::<Self>(#hook); });
+    let hooks = (on_add.is_some() || on_insert.is_some() || on_remove.is_some()).then(|| {
+        quote! {
+            fn register_hooks(hooks: &mut bifrost_ecs::core::hooks::ComponentHooks) {
+                #on_add
+                #on_insert
+                #on_remove
+            }
+        }
+    });
+
+    let required = attributes.required.iter().map(|(required, constructor)| {
+        let constructor = match constructor {
+            Some(constructor) => quote! { #constructor },
+            None => quote! { <#required as ::std::default::Default>::default },
+        };
+        quote! {
+            components.register_required::<Self, #required>(#constructor)?;
+        }
+    });
+    let required = (!attributes.required.is_empty()).then(|| {
+        quote! {
+            fn register_required(
+                components: &mut bifrost_ecs::core::component::Components,
+            ) -> ::std::result::Result<(), bifrost_ecs::core::errors::ComponentError> {
+                #(#required)*
+                ::std::result::Result::Ok(())
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics bifrost_ecs::core::component::Component for #name #ty_generics
+            #where_clause
+        {
+            #storage
+            #hooks
+            #required
+        }
+    })
+}
+
+// every field is a component, unless it is marked with #[bundle] and is a bundle itself
+#[proc_macro_derive(Bundle, attributes(bundle))]
+pub fn bundle_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse(input).unwrap();
+    impl_bundle_macro(&ast)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn impl_bundle_macro(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let syn::Data::Struct(data) = &ast.data else {
+        return Err(syn::Error::new_spanned(
+            ast,
+            "Bundle can only be derived for structs",
+        ));
+    };
+
+    let mut component_ids = Vec::new();
+    let mut get_components = Vec::new();
+    let mut from_components = Vec::new();
+    let mut component_types = Vec::new();
+    for (index, field) in data.fields.iter().enumerate() {
+        let ty = &field.ty;
+        let member = match &field.ident {
+            Some(ident) => syn::Member::Named(ident.clone()),
+            None => syn::Member::Unnamed(index.into()),
+        };
+        if field.attrs.iter().any(|attr| attr.path().is_ident("bundle")) {
+            component_ids.push(quote! {
+                <#ty as bifrost_ecs::core::component::ComponentBundle>::component_ids(
+                    components, ids,
+                )?;
+            });
+            get_components.push(quote! {
+                bifrost_ecs::core::component::ComponentBundle::get_components(
+                    self.#member, func,
+                );
+            });
+            from_components.push(quote! {
+                #member: <#ty as bifrost_ecs::core::component::ComponentBundle>::from_components(
+                    func,
+                ),
+            });
+        } else {
+            component_ids.push(quote! {
+                ids.push(components.register::<#ty>()?);
+            });
+            get_components.push(quote! {
+                let mut component = ::std::mem::ManuallyDrop::new(self.#member);
+                func(&mut *component as *mut #ty as *mut u8);
+            });
+            from_components.push(quote! {
+                #member: func().cast::<#ty>().read(),
+            });
+            component_types.push(ty);
+        }
+    }
+
+    // two impls of the same trait for the same type don't compile, so a component type
+    // given twice is reported right at the field, nested bundles are checked on spawn
+    let unique_check = ast.generics.params.is_empty().then(|| {
+        let impls = component_types.iter().map(|ty| {
+            quote::quote_spanned! { syn::spanned::Spanned::span(ty) =>
+                impl ComponentAppearsOnceInBundle<#ty> for Bundle {}
+            }
+        });
+        quote! {
+            const _: () = {
+                trait ComponentAppearsOnceInBundle<T> {}
+                struct Bundle;
+                #(#impls)*
+            };
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics bifrost_ecs::core::component::ComponentBundle for #name #ty_generics
+            #where_clause
+        {
+            fn component_ids(
+                components: &mut bifrost_ecs::core::component::Components,
+                ids: &mut ::std::vec::Vec<bifrost_ecs::core::component::ComponentId>,
+            ) -> ::std::result::Result<(), bifrost_ecs::core::errors::ComponentError> {
+                #(#component_ids)*
+                ::std::result::Result::Ok(())
+            }
+
+            fn get_components(self, func: &mut impl FnMut(*mut u8)) {
+                #(#get_components)*
+            }
+
+            unsafe fn from_components(func: &mut impl FnMut() -> *mut u8) -> Self {
+                Self {
+                    #(#from_components)*
+                }
+            }
+        }
+
+        #unique_check
+    })
+}
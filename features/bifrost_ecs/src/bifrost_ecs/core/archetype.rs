@@ -1,68 +1,1084 @@
-
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use super::{
-    component::Component,
-    entity::EntityStorage,
-    errors::{ArchetypeError, EntityAlreadyHaveComponent, EntityNotFounded},
-    query::{Fetch, Query, QueryFetched},
+    change_detection::{SystemTicks, Tick, CHECK_TICK_THRESHOLD},
+    component::{
+        Component, ComponentBundle, ComponentId, Components, RequiredComponent, StorageType,
+    },
+    entity::{Entities, Entity, EntityId, EntityLocation, EntityStorage},
+    errors::{
+        ArchetypeError, ComponentAlreadyExists, ComponentError, ComponentNotClonable,
+        EntityAlreadyHaveComponent, EntityMissingComponent,
+    },
+    disabled::Disabled,
+    hierarchy::{Children, Parent},
+    hooks::{Hooks, TakenComponents},
+    query::{Fetch, Query, QueryFilter, QueryIter, QueryState, ReadOnlyFetch, ReadOnlyQuery},
+    relation::RelationIndex,
+    removal_detection::RemovedComponentEvents,
+    resource::Resources,
+    storage::{Column, SparseSet},
 };
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ArchetypeId(usize);
+
+impl ArchetypeId {
+    pub const EMPTY: ArchetypeId = ArchetypeId(0);
+    pub(crate) const INVALID: ArchetypeId = ArchetypeId(usize::MAX);
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+// the archetype graph, where an entity of this archetype goes when a component is added or removed
+#[derive(Default)]
+struct ArchetypeEdges {
+    add: HashMap<ComponentId, ArchetypeId>,
+    remove: HashMap<ComponentId, ArchetypeId>,
+}
+
+// all entities with exactly the same set of components, each component type lives in its own column
+// and the row of an entity is the same in every column
 pub struct Archetype {
-    pub entities: Vec<EntityStorage>,
+    id: ArchetypeId,
+    component_ids: Vec<ComponentId>,
+    columns: HashMap<ComponentId, Column>,
+    entities: Vec<Entity>,
+    edges: ArchetypeEdges,
 }
 
 impl Archetype {
-    pub fn new() -> Self {
+    fn new(id: ArchetypeId, component_ids: Vec<ComponentId>, components: &Components) -> Self {
+        let columns = component_ids
+            .iter()
+            .map(|id| (*id, Column::new(components.get_info(*id).unwrap())))
+            .collect();
         Self {
+            id,
+            component_ids,
+            columns,
             entities: Vec::new(),
+            edges: ArchetypeEdges::default(),
+        }
+    }
+
+    pub fn id(&self) -> ArchetypeId {
+        self.id
+    }
+
+    pub fn component_ids(&self) -> &[ComponentId] {
+        &self.component_ids
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn contains(&self, component_id: ComponentId) -> bool {
+        self.columns.contains_key(&component_id)
+    }
+
+    pub fn get_column(&self, component_id: ComponentId) -> Option<&Column> {
+        self.columns.get(&component_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    // returns the entity that was moved into `row` to fill the hole
+    // components with an on_remove hook are handed to the hooks instead of being dropped
+    fn swap_remove(
+        &mut self,
+        row: usize,
+        components: &Components,
+        hooks: &mut Hooks,
+    ) -> Option<Entity> {
+        let entity = self.entities[row];
+        for (component_id, column) in self.columns.iter_mut() {
+            let info = components.get_info(*component_id).unwrap();
+            if info.hooks().has_on_remove() {
+                unsafe { hooks.retire(info, entity, column, row) };
+            } else {
+                column.swap_remove_and_drop(row);
+            }
+        }
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+// tells the archetypes of different scenes apart
+static NEXT_ARCHETYPES_ID: AtomicUsize = AtomicUsize::new(0);
+
+// sparse set components are not part of the archetype an entity belongs to,
+// they are kept by component id next to the archetypes
+pub struct Archetypes {
+    id: usize,
+    archetypes: Vec<Archetype>,
+    index: HashMap<Vec<ComponentId>, ArchetypeId>,
+    sparse_sets: HashMap<ComponentId, SparseSet>,
+    pub(crate) components: Components,
+    pub(crate) relations: RelationIndex,
+    pub(crate) hooks: Hooks,
+    pub(crate) removed: RemovedComponentEvents,
+    pub(crate) resources: Resources,
+    allocator: Entities,
+    disabled: ComponentId,
+    entity_component: ComponentId,
+    change_tick: Tick,
+    last_check_tick: Tick,
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Archetypes {
+    pub fn new() -> Self {
+        let mut components = Components::new();
+        let (disabled, entity_component) = register_engine_components(&mut components)
+            .expect("engine components don't require other components");
+        let empty = Archetype::new(ArchetypeId::EMPTY, Vec::new(), &components);
+        let mut index = HashMap::new();
+        index.insert(Vec::new(), ArchetypeId::EMPTY);
+        Self {
+            id: NEXT_ARCHETYPES_ID.fetch_add(1, Ordering::Relaxed),
+            archetypes: vec![empty],
+            index,
+            sparse_sets: HashMap::new(),
+            components,
+            relations: RelationIndex::default(),
+            hooks: Hooks::default(),
+            removed: RemovedComponentEvents::default(),
+            resources: Resources::default(),
+            allocator: Entities::new(),
+            disabled,
+            entity_component,
+            // systems start with a last run of 0 and see everything spawned before them
+            change_tick: Tick::new(1),
+            last_check_tick: Tick::new(1),
+        }
+    }
+
+    pub fn components(&self) -> &Components {
+        &self.components
+    }
+
+    // unique among every archetypes created by the process
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn register_component<T: Component>(
+        &mut self,
+        storage_type: StorageType,
+    ) -> Result<ComponentId, ComponentError> {
+        self.components.register_with_storage::<T>(storage_type)
+    }
+
+    pub fn register_clone<T: Component + Clone>(&mut self) -> Result<ComponentId, ComponentError> {
+        self.components.register_clone::<T>()
+    }
+
+    pub fn register_default<T: Component + Default>(
+        &mut self,
+    ) -> Result<ComponentId, ComponentError> {
+        self.components.register_default::<T>()
+    }
+
+    pub fn register_debug<T: Component + std::fmt::Debug>(
+        &mut self,
+    ) -> Result<ComponentId, ComponentError> {
+        self.components.register_debug::<T>()
+    }
+
+    // entities getting a `T` also get a `R` built by `constructor` if they don't have one
+    pub fn register_required<T: Component, R: Component>(
+        &mut self,
+        constructor: impl Fn() -> R + Send + Sync + 'static,
+    ) -> Result<(), ComponentError> {
+        self.components.register_required::<T, R>(constructor)
+    }
+
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick
+    }
+
+    // called after every system run so later changes are newer than its last run
+    pub(crate) fn increment_change_tick(&mut self) {
+        self.change_tick.increment();
+    }
+
+    // clamps every stored tick too old to be compared with the current one, only walks the
+    // storages once `CHECK_TICK_THRESHOLD` ticks passed since the last time. true when it did
+    pub fn check_change_ticks(&mut self) -> bool {
+        let this_run = self.change_tick;
+        if this_run.get().wrapping_sub(self.last_check_tick.get()) < CHECK_TICK_THRESHOLD {
+            return false;
+        }
+        for archetype in &mut self.archetypes {
+            for column in archetype.columns.values_mut() {
+                column.check_change_ticks(this_run);
+            }
+        }
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.check_change_ticks(this_run);
+        }
+        self.resources.check_change_ticks(this_run);
+        self.removed.check_change_ticks(this_run);
+        self.last_check_tick = this_run;
+        true
+    }
+
+    // disabled entities are only visited when the query asks for them
+    pub fn is_queryable(&self, archetype: &Archetype, include_disabled: bool) -> bool {
+        !archetype.is_empty() && (include_disabled || !self.is_disabled_archetype(archetype))
+    }
+
+    pub fn is_disabled_archetype(&self, archetype: &Archetype) -> bool {
+        archetype.contains(self.disabled)
+    }
+
+    pub fn get_sparse_set(&self, component_id: ComponentId) -> Option<&SparseSet> {
+        self.sparse_sets.get(&component_id)
+    }
+
+    fn storage_type(&self, component_id: ComponentId) -> StorageType {
+        self.components
+            .get_info(component_id)
+            .unwrap()
+            .storage_type()
+    }
+
+    fn sparse_set_mut(&mut self, component_id: ComponentId) -> &mut SparseSet {
+        let components = &self.components;
+        self.sparse_sets
+            .entry(component_id)
+            .or_insert_with(|| SparseSet::new(components.get_info(component_id).unwrap()))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn get(&self, id: ArchetypeId) -> Option<&Archetype> {
+        self.archetypes.get(id.0)
+    }
+
+    pub fn is_alive(&self, entity_id: EntityId) -> bool {
+        self.allocator.is_alive(entity_id)
+    }
+
+    pub fn reserve_entity(&self) -> Entity {
+        self.allocator.reserve()
+    }
+
+    // places every reserved entity in the archetype that only holds the `Entity` component
+    pub fn flush(&mut self) {
+        if !self.allocator.needs_flush() {
+            return;
+        }
+        let entity_component_id = self.entity_component;
+        let archetype_id = self.get_or_create_archetype(vec![entity_component_id]);
+        let tick = self.change_tick;
+        let archetype = &mut self.archetypes[archetype_id.0];
+        self.allocator.flush(|entity, location| {
+            let mut entity_component = entity;
+            unsafe {
+                archetype
+                    .columns
+                    .get_mut(&entity_component_id)
+                    .unwrap()
+                    .push(&mut entity_component as *mut Entity as *mut u8, tick)
+            };
+            archetype.entities.push(entity);
+            *location = EntityLocation {
+                archetype_id,
+                row: archetype.entities.len() - 1,
+            };
+        });
+    }
+
+    // number of live entities
+    pub fn len(&self) -> usize {
+        self.allocator.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocator.is_empty()
+    }
+
+    fn get_or_create_archetype(&mut self, component_ids: Vec<ComponentId>) -> ArchetypeId {
+        if let Some(id) = self.index.get(&component_ids) {
+            return *id;
+        }
+        let id = ArchetypeId(self.archetypes.len());
+        self.archetypes
+            .push(Archetype::new(id, component_ids.clone(), &self.components));
+        self.index.insert(component_ids, id);
+        id
+    }
+
+    fn archetype_with(&mut self, from: ArchetypeId, component_id: ComponentId) -> ArchetypeId {
+        if let Some(to) = self.archetypes[from.0].edges.add.get(&component_id) {
+            return *to;
+        }
+        let mut component_ids = self.archetypes[from.0].component_ids.clone();
+        component_ids.push(component_id);
+        component_ids.sort();
+        let to = self.get_or_create_archetype(component_ids);
+        self.archetypes[from.0].edges.add.insert(component_id, to);
+        self.archetypes[to.0].edges.remove.insert(component_id, from);
+        to
+    }
+
+    fn archetype_without(&mut self, from: ArchetypeId, component_id: ComponentId) -> ArchetypeId {
+        if let Some(to) = self.archetypes[from.0].edges.remove.get(&component_id) {
+            return *to;
         }
+        let component_ids = self.archetypes[from.0]
+            .component_ids
+            .iter()
+            .copied()
+            .filter(|id| *id != component_id)
+            .collect();
+        let to = self.get_or_create_archetype(component_ids);
+        self.archetypes[from.0].edges.remove.insert(component_id, to);
+        self.archetypes[to.0].edges.add.insert(component_id, from);
+        to
     }
 
-    pub fn spawn(&mut self, entity: EntityStorage) {
-        self.entities.push(entity);
+    pub fn get_location(&self, entity_id: EntityId) -> Result<EntityLocation, ArchetypeError> {
+        self.allocator.get_location(entity_id)
     }
 
-    pub fn remove_entity(&mut self, entity_id: u32) {
-        self.entities.retain(|e| e.id != entity_id);
+    // moves the entity to another archetype, columns missing in the destination are dropped
+    fn move_entity(&mut self, location: EntityLocation, to: ArchetypeId) -> EntityLocation {
+        self.move_entity_taking(location, to, None)
     }
 
-    pub fn remove_component_from_entity<T: Component>(&mut self, entity_id: u32) {
-        if let Some(entity) = self.entities.iter_mut().find(|e| e.id == entity_id) {
-            entity.remove_component::<T>();
+    // same as `move_entity` but the values found in `taken` are moved there instead
+    fn move_entity_taking(
+        &mut self,
+        location: EntityLocation,
+        to: ArchetypeId,
+        mut taken: Option<&mut TakenComponents>,
+    ) -> EntityLocation {
+        let row = location.row;
+        let (src, dst) = get_two_mut(&mut self.archetypes, location.archetype_id, to);
+        let entity = src.entities[row];
+        for (component_id, column) in src.columns.iter_mut() {
+            if let Some(dst_column) = dst.columns.get_mut(component_id) {
+                unsafe { column.swap_remove_into(row, dst_column) };
+                continue;
+            }
+            self.removed.record(*component_id, entity, self.change_tick);
+            let taken_column = taken.as_mut().and_then(|it| it.column_mut(*component_id));
+            if let Some(taken_column) = taken_column {
+                unsafe { column.swap_remove_into(row, taken_column) };
+                continue;
+            }
+            let info = self.components.get_info(*component_id).unwrap();
+            if info.hooks().has_on_remove() {
+                unsafe { self.hooks.retire(info, entity, column, row) };
+            } else {
+                column.swap_remove_and_drop(row);
+            }
         }
+        src.entities.swap_remove(row);
+        let swapped = src.entities.get(row).copied();
+        dst.entities.push(entity);
+        let new_location = EntityLocation {
+            archetype_id: to,
+            row: dst.entities.len() - 1,
+        };
+        if let Some(swapped) = swapped {
+            self.allocator.set_location(swapped, location);
+        }
+        self.allocator.set_location(entity, new_location);
+        new_location
+    }
+
+    pub fn spawn<B: ComponentBundle>(&mut self, bundle: B) -> Result<Entity, ComponentError> {
+        let (entity, bundle_ids) = self.spawn_without_required(bundle)?;
+        self.insert_required(entity, &bundle_ids);
+        Ok(entity)
     }
 
-    pub fn add_component_to_entity<T: Component + Send + Sync>(
+    // the required components are left to the caller, it gets the ids to pass to `insert_required`
+    pub(crate) fn spawn_without_required<B: ComponentBundle>(
         &mut self,
-        entity_id: u32,
+        bundle: B,
+    ) -> Result<(Entity, Vec<ComponentId>), ComponentError> {
+        self.flush();
+        let mut bundle_ids = Vec::new();
+        // the values are still in the bundle when it fails, they are dropped along with it
+        B::component_ids(&mut self.components, &mut bundle_ids)?;
+        let entity_component_id = self.entity_component;
+
+        let mut component_ids = bundle_ids.clone();
+        component_ids.push(entity_component_id);
+        component_ids.sort();
+        if let Some(pair) = component_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+            let name = self.components.get_info(pair[0]).unwrap().name().to_string();
+            self.drop_bundle(bundle, &bundle_ids);
+            return Err(ComponentError::ComponentAlreadyExistsError(
+                ComponentAlreadyExists(name),
+            ));
+        }
+        component_ids.retain(|id| self.storage_type(*id) == StorageType::Table);
+        for id in &bundle_ids {
+            if self.storage_type(*id) == StorageType::SparseSet {
+                self.sparse_set_mut(*id);
+            }
+        }
+
+        let archetype_id = self.get_or_create_archetype(component_ids);
+        let entity = self.allocator.alloc();
+        let tick = self.change_tick;
+        let archetype = &mut self.archetypes[archetype_id.0];
+        let sparse_sets = &mut self.sparse_sets;
+        let bundle_component_ids = bundle_ids.clone();
+        let mut bundle_ids = bundle_ids.into_iter();
+        bundle.get_components(&mut |component| {
+            let id = bundle_ids.next().unwrap();
+            match archetype.columns.get_mut(&id) {
+                Some(column) => unsafe { column.push(component, tick) },
+                None => unsafe {
+                    sparse_sets
+                        .get_mut(&id)
+                        .unwrap()
+                        .insert(entity, component, tick)
+                },
+            }
+        });
+        let mut entity_component = entity;
+        unsafe {
+            archetype
+                .columns
+                .get_mut(&entity_component_id)
+                .unwrap()
+                .push(&mut entity_component as *mut Entity as *mut u8, tick)
+        };
+        archetype.entities.push(entity);
+        let location = EntityLocation {
+            archetype_id,
+            row: archetype.entities.len() - 1,
+        };
+        self.allocator.set_location(entity, location);
+        for id in &bundle_component_ids {
+            self.hooks.added(self.components.get_info(*id).unwrap(), entity);
+        }
+        Ok((entity, bundle_component_ids))
+    }
+
+    // nothing took the values yet, they are dropped here instead of leaking
+    fn drop_bundle<B: ComponentBundle>(&self, bundle: B, bundle_ids: &[ComponentId]) {
+        let mut ids = bundle_ids.iter();
+        bundle.get_components(&mut |component| {
+            let info = self.components.get_info(*ids.next().unwrap()).unwrap();
+            if let Some(drop) = info.drop() {
+                unsafe { drop(component) };
+            }
+        });
+    }
+
+    // gives the entity the components required by `component_ids` it doesn't have yet
+    pub(crate) fn insert_required(&mut self, entity: Entity, component_ids: &[ComponentId]) {
+        let missing: Vec<RequiredComponent> = self
+            .components
+            .required_by(component_ids)
+            .into_iter()
+            .filter(|it| !self.has_component_id(entity, it.id))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let mut location = self.allocator.get_location(entity).unwrap();
+        let mut to = location.archetype_id;
+        for it in &missing {
+            if self.storage_type(it.id) == StorageType::Table {
+                to = self.archetype_with(to, it.id);
+            }
+        }
+        if to != location.archetype_id {
+            location = self.move_entity(location, to);
+        }
+        for it in &missing {
+            let constructor = |ptr| (it.constructor)(ptr);
+            match self.storage_type(it.id) {
+                StorageType::Table => unsafe {
+                    self.archetypes[location.archetype_id.0]
+                        .columns
+                        .get_mut(&it.id)
+                        .unwrap()
+                        .push_with(constructor, self.change_tick)
+                },
+                StorageType::SparseSet => unsafe {
+                    let tick = self.change_tick;
+                    self.sparse_set_mut(it.id).insert_with(entity, constructor, tick)
+                },
+            }
+            self.hooks.added(self.components.get_info(it.id).unwrap(), entity);
+        }
+    }
+
+    pub fn remove_entity(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.detach_hierarchy(entity_id)?;
+        self.detach_relations(entity_id)?;
+        let location = self.allocator.free(entity_id)?;
+        let archetype = &mut self.archetypes[location.archetype_id.0];
+        for component_id in &archetype.component_ids {
+            self.removed.record(*component_id, entity_id, self.change_tick);
+        }
+        if let Some(swapped) =
+            archetype.swap_remove(location.row, &self.components, &mut self.hooks)
+        {
+            self.allocator.set_location(swapped, location);
+        }
+        let sparse_ids: Vec<ComponentId> = self.sparse_sets.keys().copied().collect();
+        for component_id in sparse_ids {
+            self.remove_sparse(component_id, entity_id);
+        }
+        Ok(())
+    }
+
+    fn remove_sparse(&mut self, component_id: ComponentId, entity_id: EntityId) {
+        let Some(sparse_set) = self.sparse_sets.get_mut(&component_id) else {
+            return;
+        };
+        let info = self.components.get_info(component_id).unwrap();
+        let removed = if info.hooks().has_on_remove() {
+            self.hooks.retire_sparse(info, entity_id, sparse_set)
+        } else {
+            sparse_set.remove(entity_id)
+        };
+        if removed {
+            self.removed.record(component_id, entity_id, self.change_tick);
+        }
+    }
+
+    pub fn remove_component_from_entity<T: Component>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.allocator.get_location(entity_id)?;
+        let Some(component_id) = self.components.component_id::<T>() else {
+            return Ok(());
+        };
+        if self.storage_type(component_id) == StorageType::SparseSet {
+            self.remove_sparse(component_id, entity_id);
+            return Ok(());
+        }
+        let location = self.allocator.get_location(entity_id)?;
+        if !self.archetypes[location.archetype_id.0].contains(component_id) {
+            return Ok(());
+        }
+        let to = self.archetype_without(location.archetype_id, component_id);
+        self.move_entity(location, to);
+        Ok(())
+    }
+
+    // moves the components out of the entity, fails without touching it if one is missing
+    pub(crate) fn take_components(
+        &mut self,
+        entity_id: EntityId,
+        component_ids: &[ComponentId],
+    ) -> Result<TakenComponents, ArchetypeError> {
+        self.flush();
+        let location = self.allocator.get_location(entity_id)?;
+        let mut seen = HashSet::new();
+        for id in component_ids {
+            if !seen.insert(*id) || !self.has_component_id(entity_id, *id) {
+                let name = self.components.get_info(*id).unwrap().name().to_string();
+                return Err(ArchetypeError::EntityMissingComponentError(
+                    EntityMissingComponent::new(entity_id, name),
+                ));
+            }
+        }
+        let mut taken = TakenComponents::new(entity_id);
+        let mut to = location.archetype_id;
+        for id in component_ids {
+            taken.push(self.components.get_info(*id).unwrap());
+            if self.storage_type(*id) == StorageType::Table {
+                to = self.archetype_without(to, *id);
+            }
+        }
+        if to != location.archetype_id {
+            self.move_entity_taking(location, to, Some(&mut taken));
+        }
+        for id in component_ids {
+            if self.storage_type(*id) == StorageType::SparseSet {
+                let column = taken.column_mut(*id).unwrap();
+                self.sparse_sets.get_mut(id).unwrap().remove_into(entity_id, column);
+                self.removed.record(*id, entity_id, self.change_tick);
+            }
+        }
+        Ok(taken)
+    }
+
+    // swaps the value of a component the entity already has, or adds it
+    pub fn insert_or_replace<T: Component>(
+        &mut self,
+        entity_id: EntityId,
+        component: T,
+    ) -> Result<Option<T>, ArchetypeError> {
+        self.flush();
+        let component_id = self.components.register::<T>()?;
+        if !self.has_component_id(entity_id, component_id) {
+            self.add_component_to_entity(entity_id, component)?;
+            return Ok(None);
+        }
+        let mut current = self
+            .get_entity(entity_id)?
+            .get_component_mut::<T>()
+            .unwrap();
+        let previous = std::mem::replace(&mut *current, component);
+        let info = self.components.get_info(component_id).unwrap();
+        self.hooks.replaced(info, entity_id);
+        Ok(Some(previous))
+    }
+
+    pub fn add_component_to_entity<T: Component>(
+        &mut self,
+        entity_id: EntityId,
+        component: T,
+    ) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.allocator.get_location(entity_id)?;
+        let component_id = self.components.register::<T>()?;
+        let mut component = std::mem::ManuallyDrop::new(component);
+        unsafe {
+            self.add_component_by_id(entity_id, component_id, &mut *component as *mut T as *mut u8)
+        }
+    }
+
+    // same as `add_component_to_entity` but the required components are left to the caller,
+    // the entity must be alive and miss the component
+    pub(crate) fn add_component_without_required<T: Component>(
+        &mut self,
+        entity_id: EntityId,
         component: T,
+    ) -> Result<ComponentId, ComponentError> {
+        let component_id = self.components.register::<T>()?;
+        let mut component = std::mem::ManuallyDrop::new(component);
+        unsafe {
+            self.place_component(entity_id, component_id, &mut *component as *mut T as *mut u8)
+        };
+        Ok(component_id)
+    }
+
+    // all or nothing, every value is dropped if the entity already has one of the components
+    pub fn insert_bundle<B: ComponentBundle>(
+        &mut self,
+        entity_id: EntityId,
+        bundle: B,
+    ) -> Result<(), ArchetypeError> {
+        self.flush();
+        let mut bundle_ids = Vec::new();
+        B::component_ids(&mut self.components, &mut bundle_ids)?;
+        if let Err(error) = self.get_location(entity_id) {
+            self.drop_bundle(bundle, &bundle_ids);
+            return Err(error);
+        }
+        let mut seen = HashSet::new();
+        let existing = bundle_ids
+            .iter()
+            .find(|id| !seen.insert(**id) || self.has_component_id(entity_id, **id));
+        if let Some(id) = existing {
+            let name = self.components.get_info(*id).unwrap().name().to_string();
+            self.drop_bundle(bundle, &bundle_ids);
+            return Err(ArchetypeError::EntityAlreadyHaveComponentError(
+                EntityAlreadyHaveComponent::new(entity_id, name),
+            ));
+        }
+        let mut ids = bundle_ids.iter();
+        bundle.get_components(&mut |component| unsafe {
+            self.place_component(entity_id, *ids.next().unwrap(), component)
+        });
+        self.insert_required(entity_id, &bundle_ids);
+        Ok(())
+    }
+
+    // takes the value behind `component` over, it is dropped if the entity already has one
+    pub(crate) unsafe fn add_component_by_id(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        component: *mut u8,
     ) -> Result<(), ArchetypeError> {
-        if let Some(entity) = self.entities.iter_mut().find(|e| e.id == entity_id) {
-            if let Ok(_) = entity.add_component(component) {
-                return Ok(());
+        let info = self.components.get_info(component_id).unwrap();
+        if self.has_component_id(entity_id, component_id) {
+            if let Some(drop) = info.drop() {
+                drop(component);
             }
             return Err(ArchetypeError::EntityAlreadyHaveComponentError(
-                EntityAlreadyHaveComponent::new(entity_id, std::any::type_name::<T>().to_string()),
+                EntityAlreadyHaveComponent::new(entity_id, info.name().to_string()),
             ));
         }
-        Err(ArchetypeError::EntityNotFoundedError(
-            EntityNotFounded::new(entity_id),
+        self.place_component(entity_id, component_id, component);
+        self.insert_required(entity_id, &[component_id]);
+        Ok(())
+    }
+
+    // the entity must be alive and miss the component, required components are left to the caller
+    unsafe fn place_component(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        component: *mut u8,
+    ) {
+        let tick = self.change_tick;
+        if self.storage_type(component_id) == StorageType::SparseSet {
+            self.sparse_set_mut(component_id).insert(entity_id, component, tick);
+        } else {
+            let location = self.allocator.get_location(entity_id).unwrap();
+            let to = self.archetype_with(location.archetype_id, component_id);
+            self.move_entity(location, to);
+            let column = self.archetypes[to.0].columns.get_mut(&component_id).unwrap();
+            column.push(component, tick);
+        }
+        self.hooks.added(self.components.get_info(component_id).unwrap(), entity_id);
+    }
+
+    pub fn has_component_id(&self, entity_id: EntityId, component_id: ComponentId) -> bool {
+        let Ok(location) = self.get_location(entity_id) else {
+            return false;
+        };
+        match self.storage_type(component_id) {
+            StorageType::Table => self.archetypes[location.archetype_id.0].contains(component_id),
+            StorageType::SparseSet => self
+                .sparse_sets
+                .get(&component_id)
+                .is_some_and(|sparse_set| sparse_set.contains(entity_id)),
+        }
+    }
+
+    // spawns a copy of the entity and of all its descendants, the copy gets the same parent.
+    // relations are not copied, every other component needs a clone registered
+    pub fn clone_entity(&mut self, entity_id: EntityId) -> Result<Entity, ArchetypeError> {
+        self.flush();
+        // checked up front so a failure doesn't leave part of the tree cloned
+        self.check_clonable(entity_id)?;
+        for descendant in self.descendants(entity_id) {
+            self.check_clonable(descendant)?;
+        }
+        let clone = self.clone_components(entity_id);
+        let mut stack = vec![(entity_id, clone)];
+        while let Some((original, original_clone)) = stack.pop() {
+            for child in self.children_of(original) {
+                let child_clone = self.clone_components(child);
+                self.set_parent(child_clone, original_clone)?;
+                stack.push((child, child_clone));
+            }
+        }
+        if let Some(parent) = self.parent_of(entity_id) {
+            self.set_parent(clone, parent)?;
+        }
+        Ok(clone)
+    }
+
+    // components the engine writes itself, a plain copy of them would leave the links one sided
+    fn is_engine_managed(&self, component_id: ComponentId) -> bool {
+        [
+            self.components.component_id::<Entity>(),
+            self.components.component_id::<Parent>(),
+            self.components.component_id::<Children>(),
+        ]
+        .contains(&Some(component_id))
+            || self.relations.is_relation(component_id)
+    }
+
+    fn check_clonable(&self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        let location = self.get_location(entity_id)?;
+        let table_ids = self.archetypes[location.archetype_id.0].component_ids.iter().copied();
+        let sparse_ids = self
+            .sparse_sets
+            .iter()
+            .filter(|(_, sparse_set)| sparse_set.contains(entity_id))
+            .map(|(id, _)| *id);
+        for id in table_ids.chain(sparse_ids) {
+            let info = self.components.get_info(id).unwrap();
+            if !self.is_engine_managed(id) && info.clone_fn().is_none() {
+                return Err(ArchetypeError::ComponentNotClonableError(
+                    ComponentNotClonable::new(entity_id, info.name().to_string()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // the entity must have passed `check_clonable`
+    fn clone_components(&mut self, entity_id: EntityId) -> Entity {
+        let location = self.get_location(entity_id).unwrap();
+        let sparse_ids: Vec<ComponentId> = self
+            .sparse_sets
+            .iter()
+            .filter(|(_, sparse_set)| sparse_set.contains(entity_id))
+            .map(|(id, _)| *id)
+            .collect();
+        let mut target = location.archetype_id;
+        let mut clone_fns = HashMap::new();
+        let component_ids = self.archetypes[location.archetype_id.0].component_ids.clone();
+        for id in component_ids.into_iter().chain(sparse_ids.iter().copied()) {
+            if self.is_engine_managed(id) {
+                if self.storage_type(id) == StorageType::Table {
+                    target = self.archetype_without(target, id);
+                }
+                continue;
+            }
+            let clone_fn = self.components.get_info(id).unwrap().clone_fn().unwrap();
+            clone_fns.insert(id, clone_fn);
+        }
+        target = self.archetype_with(target, self.entity_component);
+        let tick = self.change_tick;
+
+        let clone = self.allocator.alloc();
+        let row = location.row;
+        let mut clone_entity = clone;
+        let entity_ptr = &mut clone_entity as *mut Entity as *mut u8;
+        let archetype = if target == location.archetype_id {
+            let archetype = &mut self.archetypes[target.0];
+            for (id, column) in archetype.columns.iter_mut() {
+                match clone_fns.get(id) {
+                    Some(clone_fn) => unsafe { column.clone_row(row, *clone_fn, tick) },
+                    None => unsafe { column.push(entity_ptr, tick) },
+                }
+            }
+            archetype
+        } else {
+            let (src, dst) = get_two_mut(&mut self.archetypes, location.archetype_id, target);
+            for (id, column) in dst.columns.iter_mut() {
+                match clone_fns.get(id) {
+                    Some(clone_fn) => {
+                        let value = src.columns[id].get_ptr(row);
+                        unsafe { column.push_with(|slot| clone_fn(value, slot), tick) }
+                    }
+                    None => unsafe { column.push(entity_ptr, tick) },
+                }
+            }
+            dst
+        };
+        archetype.entities.push(clone);
+        let clone_location = EntityLocation {
+            archetype_id: target,
+            row: archetype.entities.len() - 1,
+        };
+        self.allocator.set_location(clone, clone_location);
+        for id in sparse_ids {
+            if let Some(clone_fn) = clone_fns.get(&id) {
+                let sparse_set = self.sparse_set_mut(id);
+                unsafe { sparse_set.clone_value(entity_id, clone, *clone_fn, tick) };
+            }
+        }
+        for id in clone_fns.keys() {
+            self.hooks.added(self.components.get_info(*id).unwrap(), clone);
+        }
+        clone
+    }
+
+    pub fn get_entity(&self, entity_id: EntityId) -> Result<EntityStorage<'_>, ArchetypeError> {
+        let location = self.allocator.get_location(entity_id)?;
+        Ok(EntityStorage::new(
+            entity_id,
+            &self.archetypes[location.archetype_id.0],
+            location.row,
+            self,
         ))
     }
 
-    pub fn get_entity(&self, entity_id: u32) -> Option<&EntityStorage> {
-        self.entities.iter().find(|e| e.id == entity_id)
+    // the ticks a query run now compares against
+    pub fn system_ticks(&self, last_run: Tick) -> SystemTicks {
+        SystemTicks {
+            last_run,
+            this_run: self.change_tick,
+        }
     }
 
-    pub fn query_single<'a, T: Fetch<'a>>(&'a self) -> T::RawItem {
-        <T>::fetch_single(self)
+    // queries that only read go through a shared reference, the ones handing out `Mut`
+    // need the archetypes mutably so their items can't alias any other borrow
+    pub fn query_single<'a, T: Fetch<'a> + ReadOnlyFetch<'a>>(
+        &'a self,
+    ) -> <T as Fetch<'a>>::RawItem {
+        unsafe { <T>::fetch_single(self, false, self.system_ticks(Tick::default())) }
     }
 
-    pub fn query<'a, T: Query<'a>>(&'a self) -> QueryFetched<T::Item> {
-        <T>::get_components_in_all_entities(self)
+    pub fn query_single_mut<'a, T: Fetch<'a>>(&'a mut self) -> T::RawItem {
+        unsafe { <T>::fetch_single(self, false, self.system_ticks(Tick::default())) }
     }
 
-    pub fn len(&self) -> usize {
-        self.entities.len()
+    pub fn query<'a, T: ReadOnlyQuery<'a>>(&'a self) -> QueryIter<'a, T> {
+        self.query_filtered::<T, ()>(Tick::default())
+    }
+
+    pub fn query_mut<'a, T: Query<'a>>(&'a mut self) -> QueryIter<'a, T> {
+        self.query_filtered_mut::<T, ()>(Tick::default())
+    }
+
+    // only the entities matching `F`, `last_run` is what `Added` and `Changed` compare against
+    pub fn query_filtered<'a, T: ReadOnlyQuery<'a>, F: QueryFilter<'a>>(
+        &'a self,
+        last_run: Tick,
+    ) -> QueryIter<'a, T, F> {
+        unsafe { self.query_unchecked(false, last_run) }
+    }
+
+    pub fn query_filtered_mut<'a, T: Query<'a>, F: QueryFilter<'a>>(
+        &'a mut self,
+        last_run: Tick,
+    ) -> QueryIter<'a, T, F> {
+        unsafe { self.query_unchecked(false, last_run) }
+    }
+
+    // like `query_filtered` but only the archetypes the state matched are visited
+    pub fn query_with_state<'a, T: ReadOnlyQuery<'a>, F: QueryFilter<'a>>(
+        &'a self,
+        state: &'a mut QueryState<T, F>,
+        last_run: Tick,
+    ) -> QueryIter<'a, T, F> {
+        unsafe { self.query_with_state_unchecked(state, last_run) }
+    }
+
+    pub fn query_with_state_mut<'a, T: Query<'a>, F: QueryFilter<'a>>(
+        &'a mut self,
+        state: &'a mut QueryState<T, F>,
+        last_run: Tick,
+    ) -> QueryIter<'a, T, F> {
+        unsafe { self.query_with_state_unchecked(state, last_run) }
+    }
+
+    pub fn query_single_with_disabled<'a, T: Fetch<'a> + ReadOnlyFetch<'a>>(
+        &'a self,
+    ) -> <T as Fetch<'a>>::RawItem {
+        unsafe { <T>::fetch_single(self, true, self.system_ticks(Tick::default())) }
+    }
+
+    pub fn query_single_with_disabled_mut<'a, T: Fetch<'a>>(&'a mut self) -> T::RawItem {
+        unsafe { <T>::fetch_single(self, true, self.system_ticks(Tick::default())) }
+    }
+
+    pub fn query_with_disabled<'a, T: ReadOnlyQuery<'a>>(&'a self) -> QueryIter<'a, T> {
+        unsafe { self.query_unchecked(true, Tick::default()) }
+    }
+
+    pub fn query_with_disabled_mut<'a, T: Query<'a>>(&'a mut self) -> QueryIter<'a, T> {
+        unsafe { self.query_unchecked(true, Tick::default()) }
+    }
+
+    // the caller makes sure no other borrow of what `T` writes is alive for `'a`
+    pub(crate) unsafe fn query_unchecked<'a, T: Query<'a>, F: QueryFilter<'a>>(
+        &'a self,
+        include_disabled: bool,
+        last_run: Tick,
+    ) -> QueryIter<'a, T, F> {
+        QueryIter::new(self, include_disabled, self.system_ticks(last_run))
+    }
+
+    pub(crate) unsafe fn query_with_state_unchecked<'a, T: Query<'a>, F: QueryFilter<'a>>(
+        &'a self,
+        state: &'a mut QueryState<T, F>,
+        last_run: Tick,
+    ) -> QueryIter<'a, T, F> {
+        state.iter(self, self.system_ticks(last_run))
+    }
+}
+
+fn get_two_mut(
+    archetypes: &mut [Archetype],
+    a: ArchetypeId,
+    b: ArchetypeId,
+) -> (&mut Archetype, &mut Archetype) {
+    assert_ne!(a, b);
+    if a.0 < b.0 {
+        let (left, right) = archetypes.split_at_mut(b.0);
+        (&mut left[a.0], &mut right[0])
+    } else {
+        let (left, right) = archetypes.split_at_mut(a.0);
+        (&mut right[0], &mut left[b.0])
+    }
+}
+
+// the engine components show up in the registry with the traits they implement,
+// gives back the ids of `Disabled` and `Entity`
+fn register_engine_components(
+    components: &mut Components,
+) -> Result<(ComponentId, ComponentId), ComponentError> {
+    let disabled = components.register_clone::<Disabled>()?;
+    components.register_default::<Disabled>()?;
+    components.register_debug::<Disabled>()?;
+    let entity = components.register_debug::<Entity>()?;
+    components.register_debug::<Parent>()?;
+    components.register_debug::<Children>()?;
+    Ok((disabled, entity))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::core::test_utils::{Marker, Tracked};
+
+    fn value(archetypes: &Archetypes, entity: Entity) -> u32 {
+        archetypes.get_entity(entity).unwrap().get_component::<Tracked>().unwrap().1
+    }
+
+    #[test]
+    fn moving_between_archetypes_keeps_every_value() {
+        let counter = Arc::new(());
+        let mut archetypes = Archetypes::new();
+        let first = archetypes.spawn((Tracked(counter.clone(), 1),)).unwrap();
+        let second = archetypes.spawn((Tracked(counter.clone(), 2),)).unwrap();
+        archetypes.add_component_to_entity(first, Marker).unwrap();
+        assert_eq!(value(&archetypes, first), 1);
+        assert_eq!(value(&archetypes, second), 2);
+        assert_eq!(Arc::strong_count(&counter), 3);
+
+        archetypes.remove_component_from_entity::<Marker>(first).unwrap();
+        assert_eq!(value(&archetypes, first), 1);
+        archetypes.remove_component_from_entity::<Tracked>(second).unwrap();
+        assert_eq!(Arc::strong_count(&counter), 2);
+        archetypes.remove_entity(first).unwrap();
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn old_ticks_are_clamped() {
+        use crate::core::change_detection::MAX_CHANGE_AGE;
+
+        let mut archetypes = Archetypes::new();
+        let entity = archetypes.spawn((Marker,)).unwrap();
+        assert!(!archetypes.check_change_ticks());
+        archetypes.change_tick = Tick::new(MAX_CHANGE_AGE + 100);
+        assert!(archetypes.check_change_ticks());
+        let entity = archetypes.get_entity(entity).unwrap();
+        let ticks = entity.get_component_ticks::<Marker>().unwrap();
+        assert_eq!(ticks.added, Tick::new(100));
+        assert!(!archetypes.check_change_ticks());
+    }
+
+    #[test]
+    fn failed_bundle_insert_drops_every_value() {
+        let counter = Arc::new(());
+        let mut archetypes = Archetypes::new();
+        let entity = archetypes.spawn((Marker,)).unwrap();
+        let bundle = (Tracked(counter.clone(), 1), Marker);
+        assert!(archetypes.insert_bundle(entity, bundle).is_err());
+        assert!(archetypes.get_entity(entity).unwrap().get_component::<Tracked>().is_err());
+        assert_eq!(Arc::strong_count(&counter), 1);
     }
 }
@@ -0,0 +1,127 @@
+use std::ops::{Deref, DerefMut};
+
+// how many ticks pass between two checks for ticks about to be overtaken by the counter
+pub const CHECK_TICK_THRESHOLD: u32 = 518_400_000;
+
+// the oldest a tick gets before being clamped, checks are at most two thresholds apart
+// so no tick ever falls more than `u32::MAX` behind the current one
+pub const MAX_CHANGE_AGE: u32 = u32::MAX - (2 * CHECK_TICK_THRESHOLD - 1);
+
+// the archetypes count up one tick per system run, changes are stamped with the current one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Tick(u32);
+
+impl Tick {
+    pub const fn new(tick: u32) -> Self {
+        Self(tick)
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+
+    // compared relative to `this_run` so the counter can wrap around
+    pub fn is_newer_than(&self, last_run: Tick, this_run: Tick) -> bool {
+        this_run.0.wrapping_sub(self.0) < this_run.0.wrapping_sub(last_run.0)
+    }
+
+    pub(crate) fn increment(&mut self) {
+        self.0 = self.0.wrapping_add(1);
+    }
+
+    // a tick older than `MAX_CHANGE_AGE` would look newer than `this_run` once the counter
+    // wraps around to it, it is moved up to that age instead. true when it was clamped
+    pub fn check_tick(&mut self, this_run: Tick) -> bool {
+        if this_run.0.wrapping_sub(self.0) > MAX_CHANGE_AGE {
+            self.0 = this_run.0.wrapping_sub(MAX_CHANGE_AGE);
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn is_added(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.added.is_newer_than(last_run, this_run)
+    }
+
+    pub fn is_changed(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.changed.is_newer_than(last_run, this_run)
+    }
+
+    pub fn check_ticks(&mut self, this_run: Tick) {
+        self.added.check_tick(this_run);
+        self.changed.check_tick(this_run);
+    }
+}
+
+// the window a query compares the component ticks against, from the last run
+// of the calling system (excluded) up to the current tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SystemTicks {
+    pub last_run: Tick,
+    pub this_run: Tick,
+}
+
+// mutable access to a component that marks it as changed the first time it is written
+pub struct Mut<'a, T: ?Sized> {
+    value: &'a mut T,
+    ticks: &'a mut ComponentTicks,
+    this_run: Tick,
+}
+
+impl<'a, T: ?Sized> Mut<'a, T> {
+    pub(crate) fn new(value: &'a mut T, ticks: &'a mut ComponentTicks, this_run: Tick) -> Self {
+        Self {
+            value,
+            ticks,
+            this_run,
+        }
+    }
+
+    pub fn set_changed(&mut self) {
+        self.ticks.changed = self.this_run;
+    }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        *self.ticks
+    }
+
+    // writes through the returned reference are not recorded
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+
+    pub fn into_inner(mut self) -> &'a mut T {
+        self.set_changed();
+        self.value
+    }
+}
+
+impl<T: ?Sized> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T: ?Sized> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.set_changed();
+        self.value
+    }
+}
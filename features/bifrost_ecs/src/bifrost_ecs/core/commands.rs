@@ -0,0 +1,66 @@
+use super::{
+    component::{Component, ComponentBundle},
+    entity::{Entity, EntityId},
+    resource::Resource,
+    scene::Scene,
+};
+
+pub(crate) type Command = Box<dyn FnOnce(&mut Scene) + Send>;
+
+// structural changes queued from a shared scene, like in `Fn(&Scene)` systems and hooks.
+// they are applied in order at the next `Scene::flush`, which runs after every system.
+// the entity may be gone by the time a command is applied, that is not an error
+pub struct Commands<'a> {
+    scene: &'a Scene,
+}
+
+impl<'a> Commands<'a> {
+    pub(crate) fn new(scene: &'a Scene) -> Self {
+        Self { scene }
+    }
+
+    pub fn add(&self, command: impl FnOnce(&mut Scene) + Send + 'static) -> &Self {
+        self.scene.commands.lock().unwrap().push(Box::new(command));
+        self
+    }
+
+    // the id is usable right away, the components are added when the commands are applied
+    pub fn spawn(&self, cb: impl ComponentBundle + Send + 'static) -> Entity {
+        let entity = self.scene.reserve_entity();
+        self.add(move |scene| {
+            scene
+                .write_archetypes(|archetypes| archetypes.insert_bundle(entity, cb))
+                .unwrap();
+        });
+        entity
+    }
+
+    // replaces the component if the entity already has one
+    pub fn insert<T: Component>(&self, entity_id: EntityId, component: T) -> &Self {
+        self.add(move |scene| {
+            let _ = scene.insert_or_replace(entity_id, component);
+        })
+    }
+
+    pub fn remove<T: Component>(&self, entity_id: EntityId) -> &Self {
+        self.add(move |scene| {
+            let _ = scene.remove_component_from_entity::<T>(entity_id);
+        })
+    }
+
+    pub fn despawn(&self, entity_id: EntityId) -> &Self {
+        self.add(move |scene| {
+            let _ = scene.remove_entity(entity_id);
+        })
+    }
+
+    pub fn despawn_recursive(&self, entity_id: EntityId) -> &Self {
+        self.add(move |scene| {
+            let _ = scene.despawn_recursive(entity_id);
+        })
+    }
+
+    pub fn insert_resource<T: Resource>(&self, value: T) -> &Self {
+        self.add(move |scene| scene.insert_resource(value))
+    }
+}
@@ -1,12 +1,35 @@
 use std::{
+    alloc::Layout,
     any::{Any, TypeId},
-    collections::HashSet,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{Debug, Formatter},
+    mem::ManuallyDrop,
+    sync::Arc,
 };
 
-use super::entity;
+use super::{
+    errors::{
+        ComponentAlreadyRegistered, ComponentError, ComponentNameAlreadyUsed,
+        RequiredComponentConflict, RequiredComponentCycle,
+    },
+    hooks::ComponentHooks,
+};
+
+// the associated items are what `#[derive(Component)]` fills in from its attributes,
+// they are applied once, when the type is registered for the first time
+pub trait Component: 'static + Send + Sync {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
 
-pub trait Component: 'static + Send + Sync {}
+    fn register_hooks(_hooks: &mut ComponentHooks) {}
+
+    fn register_required(_components: &mut Components) -> Result<(), ComponentError> {
+        Ok(())
+    }
+}
 
+// every type is a component with the default configuration, turn the feature off
+// to implement `Component` by hand or through `#[derive(Component)]` instead
+#[cfg(feature = "blanket_component")]
 impl<T: 'static + Send + Sync> Component for T {}
 
 pub trait AsAny {
@@ -14,26 +37,450 @@ pub trait AsAny {
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ComponentId(usize);
+
+impl ComponentId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+// where the values of a component type live, `Table` keeps them in the archetype columns,
+// `SparseSet` keeps them outside of the archetypes so adding and removing them never moves the entity
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum StorageType {
+    #[default]
+    Table,
+    SparseSet,
+}
+
+pub struct ComponentInfo {
+    id: ComponentId,
+    name: String,
+    type_id: Option<TypeId>,
+    layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    clone: Option<unsafe fn(*const u8, *mut u8)>,
+    default: Option<unsafe fn(*mut u8)>,
+    debug: Option<DebugFn>,
+    storage_type: StorageType,
+    required: Vec<RequiredComponent>,
+    hooks: ComponentHooks,
+}
+
+// formats the value behind the pointer with its `Debug` impl
+pub type DebugFn = unsafe fn(*const u8, &mut Formatter<'_>) -> std::fmt::Result;
+
+// writes a new value of the component into the pointer
+pub(crate) type Constructor = Arc<dyn Fn(*mut u8) + Send + Sync>;
+
+// a component inserted together with the one requiring it, unless the entity already has it
+#[derive(Clone)]
+pub(crate) struct RequiredComponent {
+    pub(crate) id: ComponentId,
+    pub(crate) constructor: Constructor,
+}
+
+// a component type defined at runtime, there is no rust type behind it,
+// only a name unique among the dynamic components, a layout and a drop function
+#[derive(Clone, Debug)]
+pub struct ComponentDescriptor {
+    name: String,
+    layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    storage_type: StorageType,
+}
+
+impl ComponentDescriptor {
+    pub fn new(name: impl Into<String>, layout: Layout) -> Self {
+        Self {
+            name: name.into(),
+            layout,
+            drop: None,
+            storage_type: StorageType::default(),
+        }
+    }
+
+    // called with a pointer to each value that is dropped by the storage
+    pub fn with_drop(mut self, drop: unsafe fn(*mut u8)) -> Self {
+        self.drop = Some(drop);
+        self
+    }
+
+    pub fn with_storage(mut self, storage_type: StorageType) -> Self {
+        self.storage_type = storage_type;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl ComponentInfo {
+    fn from_descriptor(id: ComponentId, descriptor: ComponentDescriptor) -> Self {
+        Self {
+            id,
+            name: descriptor.name,
+            type_id: None,
+            layout: descriptor.layout,
+            drop: descriptor.drop,
+            clone: None,
+            default: None,
+            debug: None,
+            storage_type: descriptor.storage_type,
+            required: Vec::new(),
+            hooks: ComponentHooks::default(),
+        }
+    }
+
+    fn new<T: Component>(id: ComponentId, storage_type: StorageType) -> Self {
+        Self {
+            id,
+            name: std::any::type_name::<T>().to_string(),
+            type_id: Some(TypeId::of::<T>()),
+            layout: Layout::new::<T>(),
+            drop: if std::mem::needs_drop::<T>() {
+                Some(drop_ptr::<T>)
+            } else {
+                None
+            },
+            clone: None,
+            default: None,
+            debug: None,
+            storage_type,
+            required: Vec::new(),
+            hooks: ComponentHooks::new::<T>(),
+        }
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.type_id
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    // defined at runtime through a `ComponentDescriptor`
+    pub fn is_dynamic(&self) -> bool {
+        self.type_id.is_none()
+    }
+
+    pub fn drop(&self) -> Option<unsafe fn(*mut u8)> {
+        self.drop
+    }
+
+    pub fn storage_type(&self) -> StorageType {
+        self.storage_type
+    }
+
+    // the components this one requires directly
+    pub fn required_components(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.required.iter().map(|it| it.id)
+    }
+
+    // writes a clone of the value behind the first pointer into the second one
+    pub fn clone_fn(&self) -> Option<unsafe fn(*const u8, *mut u8)> {
+        self.clone
+    }
+
+    // writes a default value into the pointer
+    pub fn default_fn(&self) -> Option<unsafe fn(*mut u8)> {
+        self.default
+    }
+
+    pub fn debug_fn(&self) -> Option<DebugFn> {
+        self.debug
+    }
+
+    // the traits are only known once registered, see `Components::register_clone` and friends
+    pub fn is_clone(&self) -> bool {
+        self.clone.is_some()
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.default.is_some()
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.debug.is_some()
+    }
+
+    pub fn hooks(&self) -> &ComponentHooks {
+        &self.hooks
+    }
+}
+
+unsafe fn drop_ptr<T>(ptr: *mut u8) {
+    ptr.cast::<T>().drop_in_place();
+}
+
+unsafe fn clone_ptr<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone());
+}
+
+unsafe fn default_ptr<T: Default>(dst: *mut u8) {
+    dst.cast::<T>().write(T::default());
+}
+
+unsafe fn debug_ptr<T: Debug>(value: *const u8, f: &mut Formatter<'_>) -> std::fmt::Result {
+    (*value.cast::<T>()).fmt(f)
+}
+
+// every component type the archetypes know about gets a dense id,
+// archetypes are keyed by sorted lists of those ids
+pub struct Components {
+    infos: Vec<ComponentInfo>,
+    indices: HashMap<TypeId, ComponentId>,
+    dynamic_indices: HashMap<String, ComponentId>,
+}
+
+impl Default for Components {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Components {
+    pub fn new() -> Self {
+        Self {
+            infos: Vec::new(),
+            indices: HashMap::new(),
+            dynamic_indices: HashMap::new(),
+        }
+    }
+
+    // fails when the requirements declared on the type end up requiring itself
+    pub fn register<T: Component>(&mut self) -> Result<ComponentId, ComponentError> {
+        if let Some(id) = self.indices.get(&TypeId::of::<T>()) {
+            return Ok(*id);
+        }
+        self.insert_info::<T>(T::STORAGE_TYPE)
+    }
+
+    // the storage can only be chosen before the component is used for the first time
+    pub fn register_with_storage<T: Component>(
+        &mut self,
+        storage_type: StorageType,
+    ) -> Result<ComponentId, ComponentError> {
+        if let Some(id) = self.indices.get(&TypeId::of::<T>()) {
+            if self.infos[id.0].storage_type != storage_type {
+                return Err(ComponentError::ComponentAlreadyRegisteredError(
+                    ComponentAlreadyRegistered::new::<T>(),
+                ));
+            }
+            return Ok(*id);
+        }
+        self.insert_info::<T>(storage_type)
+    }
+
+    pub fn register_dynamic(
+        &mut self,
+        descriptor: ComponentDescriptor,
+    ) -> Result<ComponentId, ComponentError> {
+        if self.dynamic_indices.contains_key(descriptor.name()) {
+            return Err(ComponentError::ComponentNameAlreadyUsedError(
+                ComponentNameAlreadyUsed::new(descriptor.name()),
+            ));
+        }
+        let id = ComponentId(self.infos.len());
+        self.dynamic_indices.insert(descriptor.name().to_string(), id);
+        self.infos.push(ComponentInfo::from_descriptor(id, descriptor));
+        Ok(id)
+    }
+
+    // components are type erased inside the archetypes, cloning an entity needs this registered
+    pub fn register_clone<T: Component + Clone>(&mut self) -> Result<ComponentId, ComponentError> {
+        let id = self.register::<T>()?;
+        self.infos[id.0].clone = Some(clone_ptr::<T>);
+        Ok(id)
+    }
+
+    pub fn register_default<T: Component + Default>(
+        &mut self,
+    ) -> Result<ComponentId, ComponentError> {
+        let id = self.register::<T>()?;
+        self.infos[id.0].default = Some(default_ptr::<T>);
+        Ok(id)
+    }
+
+    pub fn register_debug<T: Component + Debug>(&mut self) -> Result<ComponentId, ComponentError> {
+        let id = self.register::<T>()?;
+        self.infos[id.0].debug = Some(debug_ptr::<T>);
+        Ok(id)
+    }
+
+    pub fn register_required<T: Component, R: Component>(
+        &mut self,
+        constructor: impl Fn() -> R + Send + Sync + 'static,
+    ) -> Result<(), ComponentError> {
+        let id = self.register::<T>()?;
+        let required = self.register::<R>()?;
+        let names = || (self.infos[id.0].name.as_str(), self.infos[required.0].name.as_str());
+        if id == required || self.requires(required, id) {
+            let (component, required) = names();
+            return Err(ComponentError::RequiredComponentCycleError(
+                RequiredComponentCycle::new(component, required),
+            ));
+        }
+        if self.infos[id.0].required.iter().any(|it| it.id == required) {
+            let (component, required) = names();
+            return Err(ComponentError::RequiredComponentConflictError(
+                RequiredComponentConflict::new(component, required),
+            ));
+        }
+        self.infos[id.0].required.push(RequiredComponent {
+            id: required,
+            constructor: Arc::new(move |ptr| unsafe { ptr.cast::<R>().write(constructor()) }),
+        });
+        Ok(())
+    }
+
+    // whether `id` requires `other`, directly or through its own requirements
+    pub fn requires(&self, id: ComponentId, other: ComponentId) -> bool {
+        self.required_by(&[id]).iter().any(|it| it.id == other)
+    }
+
+    // everything the given components require and don't provide themselves, breadth first
+    // so a component required at several depths gets the constructor of the nearest one
+    pub(crate) fn required_by(&self, component_ids: &[ComponentId]) -> Vec<RequiredComponent> {
+        let mut seen: HashSet<ComponentId> = component_ids.iter().copied().collect();
+        let mut queue: VecDeque<ComponentId> = component_ids.iter().copied().collect();
+        let mut required = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            for it in &self.infos[id.0].required {
+                if seen.insert(it.id) {
+                    queue.push_back(it.id);
+                    required.push(it.clone());
+                }
+            }
+        }
+        required
+    }
+
+    fn insert_info<T: Component>(
+        &mut self,
+        storage_type: StorageType,
+    ) -> Result<ComponentId, ComponentError> {
+        let id = ComponentId(self.infos.len());
+        self.infos.push(ComponentInfo::new::<T>(id, storage_type));
+        self.indices.insert(TypeId::of::<T>(), id);
+        T::register_hooks(&mut self.infos[id.0].hooks);
+        // a cycle between requirements declared on the types themselves can only show up here,
+        // the type is unregistered along with the ones it registered on the way
+        if let Err(error) = T::register_required(self) {
+            for info in self.infos.drain(id.0..) {
+                if let Some(type_id) = info.type_id {
+                    self.indices.remove(&type_id);
+                }
+            }
+            return Err(error);
+        }
+        Ok(id)
+    }
+
+    pub(crate) fn hooks_mut(&mut self, id: ComponentId) -> &mut ComponentHooks {
+        &mut self.infos[id.0].hooks
+    }
+
+    pub fn get_id(&self, type_id: TypeId) -> Option<ComponentId> {
+        self.indices.get(&type_id).copied()
+    }
+
+    pub fn component_id<T: Component>(&self) -> Option<ComponentId> {
+        self.get_id(TypeId::of::<T>())
+    }
+
+    pub fn get_dynamic_id(&self, name: &str) -> Option<ComponentId> {
+        self.dynamic_indices.get(name).copied()
+    }
+
+    pub fn get_info(&self, id: ComponentId) -> Option<&ComponentInfo> {
+        self.infos.get(id.0)
+    }
+
+    // every registered component, rust typed and dynamic, in id order
+    pub fn iter(&self) -> std::slice::Iter<'_, ComponentInfo> {
+        self.infos.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+}
+
 pub trait ComponentBundle {
-    fn add_components_to_entity(
-        self,
-        unique_instances: &HashSet<TypeId>,
-        entity: &mut entity::EntityStorage,
-    );
+    // ids in the same order `get_components` hands the values out
+    fn component_ids(
+        components: &mut Components,
+        ids: &mut Vec<ComponentId>,
+    ) -> Result<(), ComponentError>;
+    // gives a pointer to each component, the callee takes ownership of the value
+    fn get_components(self, func: &mut impl FnMut(*mut u8));
+    // builds the bundle back reading the values `func` points to, in `component_ids` order,
+    // the bundle takes ownership of them so `func` must hand out valid values of those types
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn from_components(func: &mut impl FnMut() -> *mut u8) -> Self;
+}
+
+impl ComponentBundle for () {
+    fn component_ids(
+        _components: &mut Components,
+        _ids: &mut Vec<ComponentId>,
+    ) -> Result<(), ComponentError> {
+        Ok(())
+    }
+
+    fn get_components(self, _func: &mut impl FnMut(*mut u8)) {}
+
+    unsafe fn from_components(_func: &mut impl FnMut() -> *mut u8) -> Self {}
 }
 
 macro_rules! impl_components_bundle_for_tuples {
     ($(($name: ident, $index: tt)),*) => {
         impl<$($name: Component), *> ComponentBundle for ($($name,)*){
-            fn add_components_to_entity(self, unique_instances: &HashSet<TypeId>, entity: &mut entity::EntityStorage) {
+            fn component_ids(
+                components: &mut Components,
+                ids: &mut Vec<ComponentId>,
+            ) -> Result<(), ComponentError> {
+                $(
+                    ids.push(components.register::<$name>()?);
+                )*
+                Ok(())
+            }
+
+            fn get_components(self, func: &mut impl FnMut(*mut u8)) {
                 $(
-                    // check if component is unique
-                    if unique_instances.contains(&TypeId::of::<$name>()) {
-                        panic!("Component {:?} is unique instance held by the engine!", stringify!($name));
-                    }
-                    entity.add_component(self.$index).unwrap();
+                    let mut component = ManuallyDrop::new(self.$index);
+                    func(&mut *component as *mut $name as *mut u8);
                 )*
             }
+
+            unsafe fn from_components(func: &mut impl FnMut() -> *mut u8) -> Self {
+                ($(func().cast::<$name>().read(),)*)
+            }
         }
     };
 }
@@ -91,3 +538,36 @@ impl_components_bundle_for_tuples!(
     (J, 9),
     (K, 10)
 );
+
+// requirements are only declared on the types with the blanket impl turned off
+#[cfg(all(test, not(feature = "blanket_component")))]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct First;
+    #[derive(Default)]
+    struct Second;
+
+    impl Component for First {
+        fn register_required(components: &mut Components) -> Result<(), ComponentError> {
+            components.register_required::<Self, Second>(Second::default)
+        }
+    }
+
+    impl Component for Second {
+        fn register_required(components: &mut Components) -> Result<(), ComponentError> {
+            components.register_required::<Self, First>(First::default)
+        }
+    }
+
+    #[test]
+    fn declared_cycle_is_an_error() {
+        let mut components = Components::new();
+        assert!(components.register::<First>().is_err());
+        assert!(components.is_empty());
+        assert!(components.register::<Second>().is_err());
+        let mut ids = Vec::new();
+        assert!(<(First,)>::component_ids(&mut components, &mut ids).is_err());
+    }
+}
@@ -71,6 +71,12 @@ pub struct Timers {
     pub timers: Vec<Timer>,
 }
 
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Timers {
     pub fn new() -> Self {
         Self { timers: Vec::new() }
@@ -89,9 +95,8 @@ impl Timers {
     }
 
     pub fn stop_timer(&mut self, name: &str) {
-        self.timers
-            .iter_mut()
-            .find(|timer| timer.name == name)
-            .map(|timer| timer.stop());
+        if let Some(timer) = self.timers.iter_mut().find(|timer| timer.name == name) {
+            timer.stop();
+        }
     }
 }
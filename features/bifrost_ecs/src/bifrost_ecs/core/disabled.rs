@@ -0,0 +1,43 @@
+use super::{archetype::Archetypes, entity::EntityId, errors::ArchetypeError};
+
+// marks an entity that is kept with all its components but skipped by the queries
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Disabled;
+
+#[cfg(not(feature = "blanket_component"))]
+impl super::component::Component for Disabled {}
+
+impl Archetypes {
+    pub fn is_disabled(&self, entity_id: EntityId) -> bool {
+        self.get_entity(entity_id)
+            .is_ok_and(|entity| entity.has_component::<Disabled>())
+    }
+
+    pub fn disable(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        if self.is_disabled(entity_id) {
+            return Ok(());
+        }
+        self.add_component_to_entity(entity_id, Disabled)
+    }
+
+    pub fn enable(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.remove_component_from_entity::<Disabled>(entity_id)
+    }
+
+    // disables the entity together with all its descendants
+    pub fn disable_recursive(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.disable(entity_id)?;
+        for descendant in self.descendants(entity_id) {
+            self.disable(descendant)?;
+        }
+        Ok(())
+    }
+
+    pub fn enable_recursive(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.enable(entity_id)?;
+        for descendant in self.descendants(entity_id) {
+            self.enable(descendant)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use std::marker::PhantomData;
+
+use super::{
+    archetype::Archetypes,
+    change_detection::{ComponentTicks, Mut, Tick},
+    component::{ComponentDescriptor, ComponentId, StorageType},
+    entity::{Entity, EntityId},
+    errors::{ArchetypeError, ComponentError, EntityAlreadyHaveComponent, InvalidComponentBytes},
+    query::QueryFetched,
+};
+
+// one entity matched by a dynamic query, the values come in the order of the ids
+// the query was built from
+pub struct DynamicItem<'a> {
+    entity: Entity,
+    values: Vec<(*mut u8, *mut ComponentTicks, usize)>,
+    this_run: Tick,
+    marker: PhantomData<&'a ()>,
+}
+
+impl<'a> DynamicItem<'a> {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // borrows the item so the bytes can't be held across a `get_mut`
+    pub fn get(&self, index: usize) -> &[u8] {
+        let (value, _, size) = self.values[index];
+        unsafe { std::slice::from_raw_parts(value, size) }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Mut<'_, [u8]> {
+        let (value, ticks, size) = self.values[index];
+        unsafe { Mut::new(std::slice::from_raw_parts_mut(value, size), &mut *ticks, self.this_run) }
+    }
+}
+
+impl Archetypes {
+    pub fn register_dynamic(
+        &mut self,
+        descriptor: ComponentDescriptor,
+    ) -> Result<ComponentId, ComponentError> {
+        self.components.register_dynamic(descriptor)
+    }
+
+    // the bytes are copied in and the entity owns the value from then on, rust typed
+    // components can't be written this way since the bytes could be any value
+    pub fn insert_by_id(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        bytes: &[u8],
+    ) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.get_location(entity_id)?;
+        let info = self.components.get_info(component_id).unwrap();
+        if !info.is_dynamic() || info.layout().size() != bytes.len() {
+            return Err(ArchetypeError::InvalidComponentBytesError(
+                InvalidComponentBytes::new(info.name().to_string(), bytes.len()),
+            ));
+        }
+        // the bytes are only borrowed, so they must not reach the drop of an existing value
+        if self.has_component_id(entity_id, component_id) {
+            return Err(ArchetypeError::EntityAlreadyHaveComponentError(
+                EntityAlreadyHaveComponent::new(entity_id, info.name().to_string()),
+            ));
+        }
+        // the column copies the bytes over, so they don't need to be aligned
+        unsafe { self.add_component_by_id(entity_id, component_id, bytes.as_ptr() as *mut u8) }
+    }
+
+    // entities having every one of the components, disabled ones are skipped,
+    // panics on a rust typed component since its bytes can't be handed out,
+    // and on an id given twice since both values would alias
+    pub fn query_dynamic(
+        &mut self,
+        component_ids: &[ComponentId],
+    ) -> QueryFetched<DynamicItem<'_>> {
+        unsafe { self.query_dynamic_unchecked(component_ids) }
+    }
+
+    // the caller makes sure nothing else borrows the components for as long as the items live
+    pub(crate) unsafe fn query_dynamic_unchecked(
+        &self,
+        component_ids: &[ComponentId],
+    ) -> QueryFetched<DynamicItem<'_>> {
+        let this_run = self.change_tick();
+        let infos: Vec<_> = component_ids
+            .iter()
+            .map(|id| self.components().get_info(*id).unwrap())
+            .collect();
+        if let Some(info) = infos.iter().find(|info| !info.is_dynamic()) {
+            panic!("{} is not a dynamic component and can't be queried by id", info.name());
+        }
+        for (index, info) in infos.iter().enumerate() {
+            if infos[index + 1..].iter().any(|other| other.id() == info.id()) {
+                panic!("{} is asked for more than once in the dynamic query", info.name());
+            }
+        }
+        let mut res = Vec::new();
+        for archetype in self.iter().filter(|archetype| self.is_queryable(archetype, false)) {
+            let tables_match = infos.iter().all(|info| {
+                info.storage_type() == StorageType::SparseSet || archetype.contains(info.id())
+            });
+            if !tables_match {
+                continue;
+            }
+            for (row, entity) in archetype.entities().iter().enumerate() {
+                let values = infos
+                    .iter()
+                    .map(|info| {
+                        let size = info.layout().size();
+                        match info.storage_type() {
+                            StorageType::Table => {
+                                let column = archetype.get_column(info.id())?;
+                                let ticks = unsafe { column.ticks_ptr().add(row) };
+                                Some((column.get_ptr(row), ticks, size))
+                            }
+                            StorageType::SparseSet => {
+                                let sparse_set = self.get_sparse_set(info.id())?;
+                                let ticks = sparse_set.get_ticks_ptr(*entity)?;
+                                Some((sparse_set.get_ptr(*entity)?, ticks, size))
+                            }
+                        }
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(values) = values {
+                    res.push(DynamicItem {
+                        entity: *entity,
+                        values,
+                        this_run,
+                        marker: PhantomData,
+                    });
+                }
+            }
+        }
+        QueryFetched::new(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        alloc::Layout,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::core::test_utils::Marker;
+
+    fn health(archetypes: &mut Archetypes) -> ComponentId {
+        let descriptor = ComponentDescriptor::new("health", Layout::new::<u32>());
+        archetypes.register_dynamic(descriptor).unwrap()
+    }
+
+    #[test]
+    fn typed_components_are_not_reachable_by_id() {
+        let mut archetypes = Archetypes::new();
+        let health = health(&mut archetypes);
+        let entity = archetypes.spawn((Marker,)).unwrap();
+        archetypes.insert_by_id(entity, health, &7u32.to_ne_bytes()).unwrap();
+        let marker = archetypes.components().component_id::<Marker>().unwrap();
+
+        let storage = archetypes.get_entity(entity).unwrap();
+        assert_eq!(storage.get_by_id(health), Some(&7u32.to_ne_bytes()[..]));
+        assert!(storage.get_by_id(marker).is_none());
+        assert!(storage.get_mut_by_id(marker).is_none());
+        assert!(archetypes.insert_by_id(entity, marker, &[]).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a dynamic component")]
+    fn query_dynamic_rejects_typed_components() {
+        let mut archetypes = Archetypes::new();
+        archetypes.spawn((Marker,)).unwrap();
+        let marker = archetypes.components().component_id::<Marker>().unwrap();
+        archetypes.query_dynamic(&[marker]);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than once")]
+    fn query_dynamic_rejects_duplicate_ids() {
+        let mut archetypes = Archetypes::new();
+        let health = health(&mut archetypes);
+        let entity = archetypes.spawn((Marker,)).unwrap();
+        archetypes.insert_by_id(entity, health, &7u32.to_ne_bytes()).unwrap();
+        archetypes.query_dynamic(&[health, health]);
+    }
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn count_drop(_: *mut u8) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn inserting_twice_leaves_the_bytes_alone() {
+        let mut archetypes = Archetypes::new();
+        let descriptor = ComponentDescriptor::new("handle", Layout::new::<u32>());
+        let handle = archetypes.register_dynamic(descriptor.with_drop(count_drop)).unwrap();
+        let entity = archetypes.spawn((Marker,)).unwrap();
+        archetypes.insert_by_id(entity, handle, &1u32.to_ne_bytes()).unwrap();
+        assert!(archetypes.insert_by_id(entity, handle, &2u32.to_ne_bytes()).is_err());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+        let storage = archetypes.get_entity(entity).unwrap();
+        assert_eq!(storage.get_by_id(handle), Some(&1u32.to_ne_bytes()[..]));
+        archetypes.remove_entity(entity).unwrap();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    }
+}
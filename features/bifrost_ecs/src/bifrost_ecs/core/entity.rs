@@ -1,34 +1,28 @@
 use std::{
-    any::TypeId,
-    collections::{HashMap, HashSet},
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    fmt::{Debug, Display},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        RwLockReadGuard, RwLockWriteGuard,
+    },
 };
 
 use super::{
-    component::{AsAny, Component, ComponentBundle},
-    errors::{
-        ComponentAlreadyBorrowed, ComponentAlreadyExists, ComponentError, ComponentNotFounded,
-        ComponentUnableDowncast,
-    },
+    archetype::{Archetype, ArchetypeId, Archetypes},
+    change_detection::{ComponentTicks, Mut},
+    component::{Component, ComponentId, StorageType},
+    errors::{ArchetypeError, ComponentError, ComponentNotFounded},
 };
 
-pub type EntityId = u32;
-
-impl<T: Component> AsAny for RwLock<T> {
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
-    }
-
-    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
-        self
-    }
-}
-
+pub type EntityId = Entity;
 
-pub struct EntityStorage {
+// view of one entity inside the archetype that holds it
+pub struct EntityStorage<'a> {
     pub id: EntityId,
-    pub components: HashMap<TypeId, Box<dyn AsAny + Send + Sync>>,
+    archetype: &'a Archetype,
+    row: usize,
+    archetypes: &'a Archetypes,
 }
+
 pub trait FetchItem<'a> {
     type InnerItem;
     fn inner(&'a mut self) -> Self::InnerItem;
@@ -48,93 +42,367 @@ impl<'a, 'scene_borrow, T: 'a> FetchItem<'a> for RwLockWriteGuard<'scene_borrow,
     }
 }
 
-impl EntityStorage {
-    pub fn new(id: EntityId) -> Self {
+impl<'a> EntityStorage<'a> {
+    pub(crate) fn new(
+        id: EntityId,
+        archetype: &'a Archetype,
+        row: usize,
+        archetypes: &'a Archetypes,
+    ) -> Self {
         Self {
             id,
-            components: HashMap::new(),
+            archetype,
+            row,
+            archetypes,
         }
     }
 
-    pub fn get_component<'a, T: 'static>(&'a self) -> Result<&'a T, ComponentError> {
-        let type_id = TypeId::of::<T>();
-        if let Some(component_rw_ref) = self.components.get(&type_id) {
-            if let Some(read) = component_rw_ref.as_any().downcast_ref::<RwLock<T>>() {
-                if let Ok(mut c) = read.try_read() {
-                    let component = c.inner();
-                    let component = unsafe { std::mem::transmute::<&T, &'a T>(component) };
-                    Ok(component)
-                } else {
-                    Err(ComponentError::ComponentAlreadyBorrowedError(
-                        ComponentAlreadyBorrowed::new::<T>(),
-                    ))
-                }
-            } else {
-                Err(ComponentError::ComponentUnableDowncastError(
-                    ComponentUnableDowncast::new::<T>(),
-                ))
-            }
-        } else {
-            Err(ComponentError::ComponentNotFoundedError(
+    pub fn archetype(&self) -> &'a Archetype {
+        self.archetype
+    }
+
+    // the value and the ticks of the component
+    fn get_ptr<T: Component>(&self) -> Result<(*mut u8, *mut ComponentTicks), ComponentError> {
+        self.archetypes
+            .components()
+            .component_id::<T>()
+            .and_then(|id| self.get_ptr_by_id(id))
+            .ok_or(ComponentError::ComponentNotFoundedError(
                 ComponentNotFounded::new::<T>(),
             ))
-        }
     }
 
-    pub fn get_component_mut<'a, T: 'static>(&'a self) -> Result<&'a mut T, ComponentError> {
-        let type_id = TypeId::of::<T>();
-        if let Some(component_rw_ref) = self.components.get(&type_id) {
-            if let Some(write) = component_rw_ref.as_any().downcast_ref::<RwLock<T>>() {
-                if let Ok(mut c) = write.try_write() {
-                    let component = c.inner();
-                    let component = unsafe { std::mem::transmute::<&mut T, &'a mut T>(component) };
-                    Ok(component)
-                } else {
-                    Err(ComponentError::ComponentAlreadyBorrowedError(
-                        ComponentAlreadyBorrowed::new::<T>(),
-                    ))
-                }
-            } else {
-                Err(ComponentError::ComponentUnableDowncastError(
-                    ComponentUnableDowncast::new::<T>(),
-                ))
+    fn get_ptr_by_id(&self, id: ComponentId) -> Option<(*mut u8, *mut ComponentTicks)> {
+        match self.archetypes.components().get_info(id)?.storage_type() {
+            StorageType::Table => {
+                let column = self.archetype.get_column(id)?;
+                let ticks = unsafe { column.ticks_ptr().add(self.row) };
+                Some((column.get_ptr(self.row), ticks))
             }
+            StorageType::SparseSet => {
+                let sparse_set = self.archetypes.get_sparse_set(id)?;
+                Some((sparse_set.get_ptr(self.id)?, sparse_set.get_ticks_ptr(self.id)?))
+            }
+        }
+    }
+
+    // rust typed components are left out, their bytes may hold padding or invariants
+    fn get_dynamic_ptr(&self, id: ComponentId) -> Option<(*mut u8, *mut ComponentTicks, usize)> {
+        let info = self.archetypes.components().get_info(id)?;
+        if !info.is_dynamic() {
+            return None;
+        }
+        let (component, ticks) = self.get_ptr_by_id(id)?;
+        Some((component, ticks, info.layout().size()))
+    }
+
+    // the bytes of a dynamic component as they are laid out in memory
+    pub fn get_by_id(&self, id: ComponentId) -> Option<&'a [u8]> {
+        let (component, _, size) = self.get_dynamic_ptr(id)?;
+        Some(unsafe { std::slice::from_raw_parts(component, size) })
+    }
+
+    // writing needs the scene mutably, `EntityMut` hands these out with that borrow held
+    pub(crate) fn get_mut_by_id(&self, id: ComponentId) -> Option<Mut<'a, [u8]>> {
+        let (component, ticks, size) = self.get_dynamic_ptr(id)?;
+        Some(unsafe {
+            Mut::new(
+                std::slice::from_raw_parts_mut(component, size),
+                &mut *ticks,
+                self.archetypes.change_tick(),
+            )
+        })
+    }
+
+    pub fn get_component<T: Component>(&self) -> Result<&'a T, ComponentError> {
+        let (component, _) = self.get_ptr::<T>()?;
+        Ok(unsafe { &*(component as *const T) })
+    }
+
+    // the component is only marked as changed once it is written through the returned `Mut`
+    pub(crate) fn get_component_mut<T: Component>(&self) -> Result<Mut<'a, T>, ComponentError> {
+        let (component, ticks) = self.get_ptr::<T>()?;
+        Ok(unsafe {
+            Mut::new(
+                &mut *(component as *mut T),
+                &mut *ticks,
+                self.archetypes.change_tick(),
+            )
+        })
+    }
+
+    pub fn get_component_ticks<T: Component>(&self) -> Result<ComponentTicks, ComponentError> {
+        let (_, ticks) = self.get_ptr::<T>()?;
+        Ok(unsafe { *ticks })
+    }
+
+    pub fn has_component<T: Component>(&self) -> bool {
+        self.get_ptr::<T>().is_ok()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+#[cfg(not(feature = "blanket_component"))]
+impl Component for Entity {}
+
+impl Entity {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+impl Debug for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Entity({}v{})", self.index, self.generation)
+    }
+}
+
+// where the values of a table stored entity are, the archetype and the row inside its columns
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct EntityLocation {
+    pub archetype_id: ArchetypeId,
+    pub row: usize,
+}
+
+impl EntityLocation {
+    pub(crate) const INVALID: EntityLocation = EntityLocation {
+        archetype_id: ArchetypeId::INVALID,
+        row: usize::MAX,
+    };
+}
+
+struct EntityMeta {
+    generation: u32,
+    location: EntityLocation,
+}
+
+// hands out entity ids, a despawned index is recycled with its generation bumped
+// so handles that still point to the old entity are detected as stale,
+// it also keeps the location of every live entity indexed by the entity index.
+// `reserve` works from a shared reference: it moves `free_cursor` down through the free list
+// and, once the list is used up, past the end of `meta`, `flush` then materializes those ids
+pub struct Entities {
+    meta: Vec<EntityMeta>,
+    free: Vec<u32>,
+    free_cursor: AtomicI64,
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self {
+            meta: Vec::new(),
+            free: Vec::new(),
+            free_cursor: AtomicI64::new(0),
+        }
+    }
+
+    pub fn reserve(&self) -> Entity {
+        let cursor = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+        if cursor > 0 {
+            let index = self.free[cursor as usize - 1];
+            Entity::new(index, self.meta[index as usize].generation)
         } else {
-            Err(ComponentError::ComponentNotFoundedError(
-                ComponentNotFounded::new::<T>(),
-            ))
+            Entity::new((self.meta.len() as i64 - cursor) as u32, 0)
         }
     }
 
-    fn check_if_component_is_inside<T: Component>(&self) -> bool {
-        self.components.iter().any(|it| *it.0 == TypeId::of::<T>())
+    pub fn needs_flush(&mut self) -> bool {
+        *self.free_cursor.get_mut() != self.free.len() as i64
     }
 
-    pub fn add_component<T: Component>(&mut self, component: T) -> Result<(), ComponentError> {
-        let type_id = TypeId::of::<T>();
-        if self.check_if_component_is_inside::<T>() {
-            return Err(ComponentError::ComponentAlreadyExistsError(
-                ComponentAlreadyExists::new::<T>(),
-            ));
+    // gives every reserved entity to `init` so it can be placed, `init` sets its location
+    pub(crate) fn flush(&mut self, mut init: impl FnMut(Entity, &mut EntityLocation)) {
+        let cursor = *self.free_cursor.get_mut();
+        let old_len = self.meta.len();
+        let new_len = old_len + (-cursor).max(0) as usize;
+        self.meta.resize_with(new_len, || EntityMeta {
+            generation: 0,
+            location: EntityLocation::INVALID,
+        });
+        let reused_start = cursor.max(0) as usize;
+        for index in self.free.drain(reused_start..) {
+            let meta = &mut self.meta[index as usize];
+            init(Entity::new(index, meta.generation), &mut meta.location);
+        }
+        for index in old_len..new_len {
+            let meta = &mut self.meta[index];
+            init(Entity::new(index as u32, meta.generation), &mut meta.location);
         }
-        self.components
-            .insert(type_id, Box::new(RwLock::new(component)));
-        Ok(())
+        *self.free_cursor.get_mut() = self.free.len() as i64;
     }
 
-    pub fn add_components<T: ComponentBundle>(
-        &mut self,
-        unique_instances: &HashSet<TypeId>,
-        components: T,
-    ) {
-        components.add_components_to_entity(unique_instances, self);
+    // reserved entities must be flushed before allocating or freeing
+    pub fn alloc(&mut self) -> Entity {
+        debug_assert!(!self.needs_flush());
+        if let Some(index) = self.free.pop() {
+            *self.free_cursor.get_mut() = self.free.len() as i64;
+            Entity::new(index, self.meta[index as usize].generation)
+        } else {
+            let index = self.meta.len() as u32;
+            self.meta.push(EntityMeta {
+                generation: 0,
+                location: EntityLocation::INVALID,
+            });
+            Entity::new(index, 0)
+        }
     }
 
-    pub fn remove_component<T: Component>(&mut self) {
-        let type_id = TypeId::of::<T>();
-        self.components.remove(&type_id);
+    pub fn free(&mut self, entity: Entity) -> Result<EntityLocation, ArchetypeError> {
+        self.get_location(entity)?;
+        let meta = &mut self.meta[entity.index as usize];
+        meta.generation = meta.generation.wrapping_add(1);
+        let location = std::mem::replace(&mut meta.location, EntityLocation::INVALID);
+        self.free.push(entity.index);
+        *self.free_cursor.get_mut() = self.free.len() as i64;
+        Ok(location)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.get_location(entity).is_ok()
+    }
+
+    pub fn verify(&self, entity: Entity) -> Result<(), ArchetypeError> {
+        match self.meta.get(entity.index as usize) {
+            Some(meta) if meta.generation == entity.generation => Ok(()),
+            Some(_) => Err(ArchetypeError::entity_stale(entity)),
+            None => Err(ArchetypeError::entity_not_founded(entity)),
+        }
+    }
+
+    pub fn get_location(&self, entity: Entity) -> Result<EntityLocation, ArchetypeError> {
+        self.verify(entity)?;
+        let location = self.meta[entity.index as usize].location;
+        // reserved but not flushed yet
+        if location == EntityLocation::INVALID {
+            return Err(ArchetypeError::entity_not_founded(entity));
+        }
+        Ok(location)
+    }
+
+    // the entity must be alive, rows are updated by the archetypes every time they move an entity
+    pub(crate) fn set_location(&mut self, entity: Entity, location: EntityLocation) {
+        self.meta[entity.index as usize].location = location;
+    }
+
+    pub fn len(&self) -> usize {
+        self.meta.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Entity(pub EntityId);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(row: usize) -> EntityLocation {
+        EntityLocation {
+            archetype_id: ArchetypeId::EMPTY,
+            row,
+        }
+    }
+
+    fn spawn(entities: &mut Entities) -> Entity {
+        let entity = entities.alloc();
+        entities.set_location(entity, location(entity.index() as usize));
+        entity
+    }
+
+    fn flush(entities: &mut Entities) -> Vec<Entity> {
+        let mut flushed = Vec::new();
+        entities.flush(|entity, entity_location| {
+            *entity_location = location(entity.index() as usize);
+            flushed.push(entity);
+        });
+        flushed
+    }
+
+    #[test]
+    fn freed_index_is_reused_with_a_new_generation() {
+        let mut entities = Entities::new();
+        let first = spawn(&mut entities);
+        let second = spawn(&mut entities);
+        assert_eq!(entities.free(first).unwrap(), location(0));
+        assert!(!entities.is_alive(first));
+        assert!(entities.is_alive(second));
+
+        let reused = spawn(&mut entities);
+        assert_eq!(reused, Entity::new(0, 1));
+        assert!(entities.is_alive(reused));
+        assert!(matches!(entities.verify(first), Err(ArchetypeError::EntityStaleError(_))));
+        assert!(matches!(entities.free(first), Err(ArchetypeError::EntityStaleError(_))));
+        assert!(matches!(
+            entities.verify(Entity::new(2, 0)),
+            Err(ArchetypeError::EntityNotFoundedError(_))
+        ));
+        assert_eq!(entities.len(), 2);
+    }
+
+    #[test]
+    fn reserve_uses_the_free_list_before_growing() {
+        let mut entities = Entities::new();
+        let spawned: Vec<_> = (0..4).map(|_| spawn(&mut entities)).collect();
+        entities.free(spawned[1]).unwrap();
+        entities.free(spawned[3]).unwrap();
+
+        // only part of the free list is reserved before the flush
+        let reserved = entities.reserve();
+        assert_eq!(reserved, Entity::new(3, 1));
+        assert!(!entities.is_alive(reserved));
+        assert!(entities.needs_flush());
+        assert_eq!(flush(&mut entities), [reserved]);
+        assert!(!entities.needs_flush());
+        assert!(entities.is_alive(reserved));
+
+        let reserved = [entities.reserve(), entities.reserve(), entities.reserve()];
+        assert_eq!(reserved, [Entity::new(1, 1), Entity::new(4, 0), Entity::new(5, 0)]);
+        assert_eq!(flush(&mut entities), reserved);
+        assert!(reserved.iter().all(|entity| entities.is_alive(*entity)));
+        assert_eq!(entities.len(), 6);
+        assert_eq!(spawn(&mut entities), Entity::new(6, 0));
+    }
+
+    #[test]
+    fn free_after_flush_recycles_the_reserved_index() {
+        let mut entities = Entities::new();
+        let reserved = entities.reserve();
+        flush(&mut entities);
+        assert_eq!(entities.free(reserved).unwrap(), location(0));
+        assert!(!entities.is_alive(reserved));
+        assert!(!entities.needs_flush());
+
+        let reserved_again = entities.reserve();
+        assert_eq!(reserved_again, Entity::new(0, 1));
+        assert_eq!(flush(&mut entities), [reserved_again]);
+        assert!(entities.is_alive(reserved_again));
+        assert!(matches!(entities.free(reserved), Err(ArchetypeError::EntityStaleError(_))));
+        assert_eq!(entities.len(), 1);
+    }
+}
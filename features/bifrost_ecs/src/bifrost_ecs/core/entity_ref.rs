@@ -0,0 +1,126 @@
+use super::{
+    change_detection::Mut,
+    component::{Component, ComponentBundle, ComponentId},
+    entity::EntityId,
+    errors::{ArchetypeError, ComponentError, ComponentNotFounded},
+    scene::Scene,
+};
+
+// read access to the components of one entity
+pub struct EntityRef<'a> {
+    scene: &'a Scene,
+    id: EntityId,
+}
+
+impl<'a> EntityRef<'a> {
+    pub(crate) fn new(scene: &'a Scene, id: EntityId) -> Self {
+        Self { scene, id }
+    }
+
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    pub fn get<T: Component>(&self) -> Result<&'a T, ComponentError> {
+        get_component::<T>(self.scene, self.id)
+    }
+
+    pub fn contains<T: Component>(&self) -> bool {
+        self.get::<T>().is_ok()
+    }
+
+    pub fn get_by_id(&self, component_id: ComponentId) -> Option<&'a [u8]> {
+        self.scene.read_archetypes().get_entity(self.id).ok()?.get_by_id(component_id)
+    }
+}
+
+// read and write access to one entity, also used as builder by `Scene::spawn_empty`
+pub struct EntityMut<'a> {
+    scene: &'a mut Scene,
+    id: EntityId,
+}
+
+impl<'a> EntityMut<'a> {
+    pub(crate) fn new(scene: &'a mut Scene, id: EntityId) -> Self {
+        Self { scene, id }
+    }
+
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    pub fn get<T: Component>(&self) -> Result<&T, ComponentError> {
+        get_component::<T>(self.scene, self.id)
+    }
+
+    pub fn get_mut<T: Component>(&mut self) -> Result<Mut<'_, T>, ComponentError> {
+        self.scene
+            .read_archetypes()
+            .get_entity(self.id)
+            .map_err(|_| ComponentError::ComponentNotFoundedError(ComponentNotFounded::new::<T>()))?
+            .get_component_mut::<T>()
+    }
+
+    pub fn contains<T: Component>(&self) -> bool {
+        self.get::<T>().is_ok()
+    }
+
+    pub fn get_by_id(&self, component_id: ComponentId) -> Option<&[u8]> {
+        self.scene.read_archetypes().get_entity(self.id).ok()?.get_by_id(component_id)
+    }
+
+    pub fn get_mut_by_id(&mut self, component_id: ComponentId) -> Option<Mut<'_, [u8]>> {
+        self.scene.read_archetypes().get_entity(self.id).ok()?.get_mut_by_id(component_id)
+    }
+
+    // panics if the entity already have a component of the same type, see `try_insert`
+    pub fn insert<T: Component>(&mut self, component: T) -> &mut Self {
+        self.try_insert(component).unwrap()
+    }
+
+    pub fn try_insert<T: Component>(&mut self, component: T) -> Result<&mut Self, ArchetypeError> {
+        self.scene
+            .write_archetypes(|archetypes| archetypes.add_component_to_entity(self.id, component))?;
+        Ok(self)
+    }
+
+    pub fn remove<T: Component>(&mut self) -> &mut Self {
+        self.scene
+            .write_archetypes(|archetypes| archetypes.remove_component_from_entity::<T>(self.id))
+            .unwrap();
+        self
+    }
+
+    pub fn take<T: Component>(&mut self) -> Result<T, ArchetypeError> {
+        self.scene.take::<T>(self.id)
+    }
+
+    pub fn remove_bundle<B: ComponentBundle>(&mut self) -> Result<B, ArchetypeError> {
+        self.scene.remove_bundle::<B>(self.id)
+    }
+
+    pub fn insert_or_replace<T: Component>(
+        &mut self,
+        component: T,
+    ) -> Result<Option<T>, ArchetypeError> {
+        self.scene.insert_or_replace(self.id, component)
+    }
+
+    pub fn despawn(self) -> Result<(), ArchetypeError> {
+        self.scene
+            .write_archetypes(|archetypes| archetypes.remove_entity(self.id))
+    }
+
+    pub fn despawn_recursive(self) -> Result<(), ArchetypeError> {
+        self.scene
+            .write_archetypes(|archetypes| archetypes.remove_entity_recursive(self.id))
+    }
+}
+
+fn get_component<T: Component>(scene: &Scene, id: EntityId) -> Result<&T, ComponentError> {
+    scene
+        .read_archetypes()
+        .get_entity(id)
+        .map_err(|_| ComponentError::ComponentNotFoundedError(ComponentNotFounded::new::<T>()))?
+        .get_component::<T>()
+}
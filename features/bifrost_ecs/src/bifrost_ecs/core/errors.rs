@@ -3,16 +3,26 @@ use std::{
     fmt::{Debug, Display},
 };
 
+use super::entity::EntityId;
+
 pub struct ComponentNotFounded(String);
 pub struct ComponentAlreadyExists(pub String);
 pub struct ComponentAlreadyBorrowed(String);
 pub struct ComponentUnableDowncast(String);
+pub struct ComponentAlreadyRegistered(String);
+pub struct RequiredComponentCycle(String, String);
+pub struct RequiredComponentConflict(String, String);
+pub struct ComponentNameAlreadyUsed(String);
 
 pub enum ComponentError {
     ComponentNotFoundedError(ComponentNotFounded),
     ComponentAlreadyExistsError(ComponentAlreadyExists),
     ComponentAlreadyBorrowedError(ComponentAlreadyBorrowed),
     ComponentUnableDowncastError(ComponentUnableDowncast),
+    ComponentAlreadyRegisteredError(ComponentAlreadyRegistered),
+    RequiredComponentCycleError(RequiredComponentCycle),
+    RequiredComponentConflictError(RequiredComponentConflict),
+    ComponentNameAlreadyUsedError(ComponentNameAlreadyUsed),
 }
 
 impl ComponentAlreadyExists {
@@ -40,6 +50,30 @@ impl ComponentUnableDowncast {
     }
 }
 
+impl ComponentAlreadyRegistered {
+    pub fn new<T: 'static>() -> Self {
+        Self(std::any::type_name::<T>().to_string())
+    }
+}
+
+impl RequiredComponentCycle {
+    pub fn new(component: &str, required: &str) -> Self {
+        Self(component.to_string(), required.to_string())
+    }
+}
+
+impl RequiredComponentConflict {
+    pub fn new(component: &str, required: &str) -> Self {
+        Self(component.to_string(), required.to_string())
+    }
+}
+
+impl ComponentNameAlreadyUsed {
+    pub fn new(name: &str) -> Self {
+        Self(name.to_string())
+    }
+}
+
 impl Display for ComponentNotFounded {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Component {} not founded", self.0)
@@ -64,6 +98,30 @@ impl Display for ComponentUnableDowncast {
     }
 }
 
+impl Display for ComponentAlreadyRegistered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} already registered with another storage", self.0)
+    }
+}
+
+impl Display for RequiredComponentCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} can't require {}, it would end up requiring itself", self.0, self.1)
+    }
+}
+
+impl Display for RequiredComponentConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} already requires {}", self.0, self.1)
+    }
+}
+
+impl Display for ComponentNameAlreadyUsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component name {} is already used by another dynamic component", self.0)
+    }
+}
+
 impl Debug for ComponentNotFounded {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Component {} not founded", self.0)
@@ -89,6 +147,30 @@ impl Debug for ComponentUnableDowncast {
     }
 }
 
+impl Debug for ComponentAlreadyRegistered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} already registered with another storage", self.0)
+    }
+}
+
+impl Debug for RequiredComponentCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} can't require {}, it would end up requiring itself", self.0, self.1)
+    }
+}
+
+impl Debug for RequiredComponentConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} already requires {}", self.0, self.1)
+    }
+}
+
+impl Debug for ComponentNameAlreadyUsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component name {} is already used by another dynamic component", self.0)
+    }
+}
+
 impl Display for ComponentError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -96,6 +178,10 @@ impl Display for ComponentError {
             ComponentError::ComponentAlreadyExistsError(e) => write!(f, "{}", e),
             ComponentError::ComponentAlreadyBorrowedError(e) => write!(f, "{}", e),
             ComponentError::ComponentUnableDowncastError(e) => write!(f, "{}", e),
+            ComponentError::ComponentAlreadyRegisteredError(e) => write!(f, "{}", e),
+            ComponentError::RequiredComponentCycleError(e) => write!(f, "{}", e),
+            ComponentError::RequiredComponentConflictError(e) => write!(f, "{}", e),
+            ComponentError::ComponentNameAlreadyUsedError(e) => write!(f, "{}", e),
         }
     }
 }
@@ -107,6 +193,10 @@ impl Debug for ComponentError {
             ComponentError::ComponentAlreadyExistsError(e) => write!(f, "{:?}", e),
             ComponentError::ComponentAlreadyBorrowedError(e) => write!(f, "{:?}", e),
             ComponentError::ComponentUnableDowncastError(e) => write!(f, "{:?}", e),
+            ComponentError::ComponentAlreadyRegisteredError(e) => write!(f, "{:?}", e),
+            ComponentError::RequiredComponentCycleError(e) => write!(f, "{:?}", e),
+            ComponentError::RequiredComponentConflictError(e) => write!(f, "{:?}", e),
+            ComponentError::ComponentNameAlreadyUsedError(e) => write!(f, "{:?}", e),
         }
     }
 }
@@ -115,6 +205,10 @@ impl Error for ComponentAlreadyExists {}
 impl Error for ComponentNotFounded {}
 impl Error for ComponentAlreadyBorrowed {}
 impl Error for ComponentUnableDowncast{}
+impl Error for ComponentAlreadyRegistered {}
+impl Error for RequiredComponentCycle {}
+impl Error for RequiredComponentConflict {}
+impl Error for ComponentNameAlreadyUsed {}
 
 
 
@@ -122,29 +216,80 @@ impl Error for ComponentUnableDowncast{}
 
 pub enum ArchetypeError{
     EntityNotFoundedError(EntityNotFounded),
+    EntityStaleError(EntityStale),
     EntityAlreadyHaveComponentError(EntityAlreadyHaveComponent),
+    HierarchyCycleError(HierarchyCycle),
+    ComponentNotClonableError(ComponentNotClonable),
+    EntityMissingComponentError(EntityMissingComponent),
+    InvalidComponentBytesError(InvalidComponentBytes),
+    ComponentError(ComponentError),
 }
 
+impl From<ComponentError> for ArchetypeError{
+    fn from(error: ComponentError) -> Self{
+        Self::ComponentError(error)
+    }
+}
 
-pub struct EntityNotFounded(u32);
-pub struct EntityAlreadyHaveComponent(u32, String);
+
+pub struct EntityNotFounded(EntityId);
+pub struct EntityStale(EntityId);
+pub struct EntityAlreadyHaveComponent(EntityId, String);
+pub struct HierarchyCycle(EntityId, EntityId);
+pub struct ComponentNotClonable(EntityId, String);
+pub struct EntityMissingComponent(EntityId, String);
+pub struct InvalidComponentBytes(String, usize);
 
 impl EntityNotFounded{
-    pub fn new(entity_id: u32) -> Self{
+    pub fn new(entity_id: EntityId) -> Self{
+        Self(entity_id)
+    }
+}
+
+impl EntityStale{
+    pub fn new(entity_id: EntityId) -> Self{
         Self(entity_id)
     }
 }
 
 impl EntityAlreadyHaveComponent{
-    pub fn new(entity_id: u32, component_name: String) -> Self{
+    pub fn new(entity_id: EntityId, component_name: String) -> Self{
         Self(entity_id, component_name)
     }
 }
 
+impl HierarchyCycle{
+    pub fn new(parent: EntityId, child: EntityId) -> Self{
+        Self(parent, child)
+    }
+}
+
+impl ComponentNotClonable{
+    pub fn new(entity_id: EntityId, component_name: String) -> Self{
+        Self(entity_id, component_name)
+    }
+}
+
+impl EntityMissingComponent{
+    pub fn new(entity_id: EntityId, component_name: String) -> Self{
+        Self(entity_id, component_name)
+    }
+}
+
+impl InvalidComponentBytes{
+    pub fn new(component_name: String, len: usize) -> Self{
+        Self(component_name, len)
+    }
+}
+
 impl ArchetypeError{
-    pub fn entity_not_founded(entity_id: u32) -> Self{
+    pub fn entity_not_founded(entity_id: EntityId) -> Self{
         Self::EntityNotFoundedError(EntityNotFounded::new(entity_id))
     }
+
+    pub fn entity_stale(entity_id: EntityId) -> Self{
+        Self::EntityStaleError(EntityStale::new(entity_id))
+    }
 }
 
 impl Display for EntityNotFounded{
@@ -159,6 +304,18 @@ impl Debug for EntityNotFounded{
     }
 }
 
+impl Display for EntityStale{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntityStorage {} is stale, it was already removed", self.0)
+    }
+}
+
+impl Debug for EntityStale{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntityStorage {} is stale, it was already removed", self.0)
+    }
+}
+
 impl Display for EntityAlreadyHaveComponent{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "EntityStorage {} already have component {}", self.0, self.1)
@@ -171,12 +328,65 @@ impl Debug for EntityAlreadyHaveComponent{
     }
 }
 
+impl Display for HierarchyCycle{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntityStorage {} can't be parent of {}, it would create a cycle", self.0, self.1)
+    }
+}
+
+impl Debug for HierarchyCycle{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntityStorage {} can't be parent of {}, it would create a cycle", self.0, self.1)
+    }
+}
+
+impl Display for ComponentNotClonable{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Entity {} can't be cloned, its component {} has no clone registered", self.0, self.1)
+    }
+}
+
+impl Debug for ComponentNotClonable{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Entity {} can't be cloned, its component {} has no clone registered", self.0, self.1)
+    }
+}
+
+impl Display for EntityMissingComponent{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntityStorage {} doesn't have component {}", self.0, self.1)
+    }
+}
+
+impl Debug for EntityMissingComponent{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntityStorage {} doesn't have component {}", self.0, self.1)
+    }
+}
+
+impl Display for InvalidComponentBytes{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} can't be written from {} bytes, only dynamic components of that size can", self.0, self.1)
+    }
+}
+
+impl Debug for InvalidComponentBytes{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Component {} can't be written from {} bytes, only dynamic components of that size can", self.0, self.1)
+    }
+}
 
 impl Display for ArchetypeError{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ArchetypeError::EntityNotFoundedError(e) => write!(f, "{}", e),
+            ArchetypeError::EntityStaleError(e) => write!(f, "{}", e),
             ArchetypeError::EntityAlreadyHaveComponentError(e) => write!(f, "{}", e),
+            ArchetypeError::HierarchyCycleError(e) => write!(f, "{}", e),
+            ArchetypeError::ComponentNotClonableError(e) => write!(f, "{}", e),
+            ArchetypeError::EntityMissingComponentError(e) => write!(f, "{}", e),
+            ArchetypeError::InvalidComponentBytesError(e) => write!(f, "{}", e),
+            ArchetypeError::ComponentError(e) => write!(f, "{}", e),
         }
     }
 }
@@ -185,7 +395,13 @@ impl Debug for ArchetypeError{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ArchetypeError::EntityNotFoundedError(e) => write!(f, "{:?}", e),
+            ArchetypeError::EntityStaleError(e) => write!(f, "{:?}", e),
             ArchetypeError::EntityAlreadyHaveComponentError(e) => write!(f, "{:?}", e),
+            ArchetypeError::HierarchyCycleError(e) => write!(f, "{:?}", e),
+            ArchetypeError::ComponentNotClonableError(e) => write!(f, "{:?}", e),
+            ArchetypeError::EntityMissingComponentError(e) => write!(f, "{:?}", e),
+            ArchetypeError::InvalidComponentBytesError(e) => write!(f, "{:?}", e),
+            ArchetypeError::ComponentError(e) => write!(f, "{:?}", e),
         }
     }
 }
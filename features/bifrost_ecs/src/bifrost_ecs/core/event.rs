@@ -25,10 +25,16 @@ impl<T: EventComponent> Deref for Event<T> {
 
 pub trait EventHandle<T: EventComponent> {
     fn send(&mut self, data: T);
-    fn read<'a>(&'a mut self) -> Option<T>;
+    fn read(&mut self) -> Option<T>;
     fn clear(&mut self);
 }
 
+impl<T: EventComponent> Default for Event<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: EventComponent> Event<T> {
     pub fn new() -> Self {
         Self {
@@ -42,7 +48,7 @@ impl<T: EventComponent> EventHandle<T> for Event<T> {
         self.data.push_back(data);
     }
 
-    fn read<'a>(&'a mut self) -> Option<T> {
+    fn read(&mut self) -> Option<T> {
         self.pop_front()
     }
 
@@ -67,6 +73,12 @@ pub struct EventStorage {
     events: HashMap<TypeId, Box<dyn AsAny + Send + Sync>>,
 }
 
+impl Default for EventStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EventStorage {
     pub fn new() -> Self {
         Self {
@@ -85,9 +97,7 @@ impl EventStorage {
         event.send(data);
     }
 
-    pub fn read<'a, T: EventComponent + 'static>(
-        &'a mut self,
-    ) -> Option<T> {
+    pub fn read<T: EventComponent + 'static>(&mut self) -> Option<T> {
         let event = self.events.get_mut(&TypeId::of::<T>()).unwrap();
         let event = event.as_any_mut().downcast_mut::<Event<T>>().unwrap();
         event.read()
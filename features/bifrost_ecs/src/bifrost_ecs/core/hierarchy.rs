@@ -0,0 +1,184 @@
+use std::ops::Deref;
+
+use super::{
+    archetype::Archetypes,
+    entity::{Entity, EntityId},
+    errors::{ArchetypeError, HierarchyCycle},
+};
+
+// both components are only written by the engine so the two sides of the relation never disagree
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Parent(Entity);
+
+#[cfg(not(feature = "blanket_component"))]
+impl super::component::Component for Parent {}
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Children(Vec<Entity>);
+
+#[cfg(not(feature = "blanket_component"))]
+impl super::component::Component for Children {}
+
+impl Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Archetypes {
+    pub fn parent_of(&self, entity_id: EntityId) -> Option<Entity> {
+        self.get_entity(entity_id)
+            .ok()?
+            .get_component::<Parent>()
+            .ok()
+            .map(|parent| parent.0)
+    }
+
+    pub fn children_of(&self, entity_id: EntityId) -> Vec<Entity> {
+        self.get_entity(entity_id)
+            .ok()
+            .and_then(|entity| entity.get_component::<Children>().ok())
+            .map(|children| children.0.clone())
+            .unwrap_or_default()
+    }
+
+    // parent first, up to the root
+    pub fn ancestors(&self, entity_id: EntityId) -> Vec<Entity> {
+        let mut ancestors = Vec::new();
+        let mut current = entity_id;
+        while let Some(parent) = self.parent_of(current) {
+            ancestors.push(parent);
+            current = parent;
+        }
+        ancestors
+    }
+
+    // depth first, every entity comes before its own children
+    pub fn descendants(&self, entity_id: EntityId) -> Vec<Entity> {
+        let mut descendants = Vec::new();
+        let mut stack = self.children_of(entity_id);
+        stack.reverse();
+        while let Some(entity) = stack.pop() {
+            descendants.push(entity);
+            stack.extend(self.children_of(entity).into_iter().rev());
+        }
+        descendants
+    }
+
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.get_location(child)?;
+        self.get_location(parent)?;
+        if child == parent || self.ancestors(parent).contains(&child) {
+            return Err(ArchetypeError::HierarchyCycleError(HierarchyCycle::new(
+                parent, child,
+            )));
+        }
+        if self.parent_of(child) == Some(parent) {
+            return Ok(());
+        }
+        self.remove_parent(child)?;
+        self.add_component_to_entity(child, Parent(parent))?;
+        let children = self
+            .get_entity(parent)?
+            .get_component_mut::<Children>()
+            .ok();
+        match children {
+            Some(mut children) => children.0.push(child),
+            None => self.add_component_to_entity(parent, Children(vec![child]))?,
+        }
+        Ok(())
+    }
+
+    pub fn remove_parent(&mut self, child: EntityId) -> Result<(), ArchetypeError> {
+        self.flush();
+        let Some(parent) = self.parent_of(child) else {
+            return Ok(());
+        };
+        self.remove_component_from_entity::<Parent>(child)?;
+        let children = self.get_entity(parent)?.get_component_mut::<Children>().ok();
+        if let Some(mut children) = children {
+            children.0.retain(|it| *it != child);
+            if children.0.is_empty() {
+                self.remove_component_from_entity::<Children>(parent)?;
+            }
+        }
+        Ok(())
+    }
+
+    // removes the entity together with all its descendants,
+    // a relation cleanup may already have despawned some of them along the way
+    pub fn remove_entity_recursive(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.get_location(entity_id)?;
+        for descendant in self.descendants(entity_id).into_iter().rev() {
+            if self.is_alive(descendant) {
+                self.remove_entity(descendant)?;
+            }
+        }
+        if self.is_alive(entity_id) {
+            self.remove_entity(entity_id)?;
+        }
+        Ok(())
+    }
+
+    // unlinks the entity from its parent and orphans its children before it is removed
+    pub(crate) fn detach_hierarchy(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.remove_parent(entity_id)?;
+        for child in self.children_of(entity_id) {
+            self.remove_component_from_entity::<Parent>(child)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        relation::{Relation, RelationCleanup},
+        test_utils::Marker,
+    };
+
+    // the source is despawned together with the entity it guards
+    struct Guards;
+
+    #[cfg(not(feature = "blanket_component"))]
+    impl crate::core::component::Component for Guards {}
+
+    impl Relation for Guards {
+        const CLEANUP: RelationCleanup = RelationCleanup::DespawnSource;
+    }
+
+    #[test]
+    fn despawn_recursive_removes_the_whole_subtree() {
+        let mut archetypes = Archetypes::new();
+        let parent = archetypes.spawn((Marker,)).unwrap();
+        let child = archetypes.spawn((Marker,)).unwrap();
+        let grandchild = archetypes.spawn((Marker,)).unwrap();
+        let guard = archetypes.spawn((Marker,)).unwrap();
+        let guarded = archetypes.spawn((Marker,)).unwrap();
+        archetypes.set_parent(child, parent).unwrap();
+        archetypes.set_parent(grandchild, child).unwrap();
+        archetypes.set_parent(guard, parent).unwrap();
+        archetypes.set_parent(guarded, parent).unwrap();
+        // removing `guarded` despawns `guard` before the loop reaches it
+        archetypes.add_relation(guard, guarded, Guards).unwrap();
+        let other = archetypes.spawn((Marker,)).unwrap();
+
+        archetypes.remove_entity_recursive(parent).unwrap();
+        for entity in [parent, child, grandchild, guard, guarded] {
+            assert!(!archetypes.is_alive(entity));
+        }
+        assert!(archetypes.is_alive(other));
+        assert!(archetypes.children_of(other).is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+use std::{any::TypeId, collections::HashMap, sync::Arc};
+
+use super::{
+    component::{Component, ComponentBundle, ComponentId, ComponentInfo, Components},
+    entity::Entity,
+    scene::Scene,
+    storage::{Column, SparseSet},
+};
+
+pub type ComponentHook = Arc<dyn Fn(&Scene, Entity) + Send + Sync>;
+// the removed value is handed over type erased, `ComponentHooks::on_remove` casts it back
+pub(crate) type RemoveHook = Arc<dyn Fn(&Scene, Entity, *mut u8) + Send + Sync>;
+
+// the hooks of a single component type, kept in its `ComponentInfo`,
+// the `Scene::on_add` family describes when each of them runs
+#[derive(Default, Clone)]
+pub struct ComponentHooks {
+    // the Rust type of the component, `on_remove` only accepts hooks for that type
+    type_id: Option<TypeId>,
+    on_add: Option<ComponentHook>,
+    on_insert: Option<ComponentHook>,
+    on_remove: Option<RemoveHook>,
+}
+
+impl ComponentHooks {
+    pub(crate) fn new<T: Component>() -> Self {
+        Self {
+            type_id: Some(TypeId::of::<T>()),
+            ..Default::default()
+        }
+    }
+
+    pub fn on_add(&mut self, hook: impl Fn(&Scene, Entity) + Send + Sync + 'static) -> &mut Self {
+        self.on_add = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_insert(
+        &mut self,
+        hook: impl Fn(&Scene, Entity) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_insert = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_remove<T: Component>(
+        &mut self,
+        hook: impl Fn(&Scene, Entity, &T) + Send + Sync + 'static,
+    ) -> &mut Self {
+        assert!(
+            self.type_id == Some(TypeId::of::<T>()),
+            "the on_remove hook takes {}, which is not the component it is registered for",
+            std::any::type_name::<T>()
+        );
+        self.on_remove = Some(Arc::new(move |scene, entity, value| {
+            hook(scene, entity, unsafe { &*(value as *const T) })
+        }));
+        self
+    }
+
+    pub fn has_on_remove(&self) -> bool {
+        self.on_remove.is_some()
+    }
+}
+
+enum HookEvent {
+    Add(ComponentId, Entity),
+    Insert(ComponentId, Entity),
+    // the row of the value in the removed column of the component
+    Remove(ComponentId, Entity, usize),
+}
+
+impl HookEvent {
+    fn component_id(&self) -> ComponentId {
+        match *self {
+            HookEvent::Add(id, _) | HookEvent::Insert(id, _) | HookEvent::Remove(id, _, _) => id,
+        }
+    }
+}
+
+// the archetypes only queue what happened to components with hooks
+// and the scene runs the hooks once the archetypes are unlocked again
+#[derive(Default)]
+pub struct Hooks {
+    events: Vec<HookEvent>,
+    // values whose on_remove hook didn't run yet, they are dropped right after it
+    removed: HashMap<ComponentId, Column>,
+}
+
+impl Hooks {
+    // the entity got a component it didn't have before
+    pub(crate) fn added(&mut self, info: &ComponentInfo, entity: Entity) {
+        let (component_id, hooks) = (info.id(), info.hooks());
+        if hooks.on_add.is_some() {
+            self.events.push(HookEvent::Add(component_id, entity));
+        }
+        if hooks.on_insert.is_some() {
+            self.events.push(HookEvent::Insert(component_id, entity));
+        }
+    }
+
+    // the entity already had the component and only its value was swapped
+    pub(crate) fn replaced(&mut self, info: &ComponentInfo, entity: Entity) {
+        if info.hooks().on_insert.is_some() {
+            self.events.push(HookEvent::Insert(info.id(), entity));
+        }
+    }
+
+    // keeps the value at `row` alive until the on_remove hook has seen it
+    pub(crate) unsafe fn retire(
+        &mut self,
+        info: &ComponentInfo,
+        entity: Entity,
+        column: &mut Column,
+        row: usize,
+    ) {
+        let removed = self
+            .removed
+            .entry(info.id())
+            .or_insert_with(|| Column::new(info));
+        column.swap_remove_into(row, removed);
+        self.events
+            .push(HookEvent::Remove(info.id(), entity, removed.len() - 1));
+    }
+
+    pub(crate) fn retire_sparse(
+        &mut self,
+        info: &ComponentInfo,
+        entity: Entity,
+        sparse_set: &mut SparseSet,
+    ) -> bool {
+        let removed = self
+            .removed
+            .entry(info.id())
+            .or_insert_with(|| Column::new(info));
+        if !sparse_set.remove_into(entity, removed) {
+            return false;
+        }
+        self.events
+            .push(HookEvent::Remove(info.id(), entity, removed.len() - 1));
+        true
+    }
+
+    pub(crate) fn take_pending(&mut self, components: &Components) -> Option<PendingHooks> {
+        if self.events.is_empty() {
+            return None;
+        }
+        let hooks = self
+            .events
+            .iter()
+            .map(|event| {
+                let id = event.component_id();
+                (id, components.get_info(id).unwrap().hooks().clone())
+            })
+            .collect();
+        Some(PendingHooks {
+            hooks,
+            events: std::mem::take(&mut self.events),
+            removed: std::mem::take(&mut self.removed),
+        })
+    }
+}
+
+pub(crate) struct PendingHooks {
+    hooks: HashMap<ComponentId, ComponentHooks>,
+    events: Vec<HookEvent>,
+    removed: HashMap<ComponentId, Column>,
+}
+
+impl PendingHooks {
+    // runs in the order the changes happened, the removed values are dropped afterwards
+    pub(crate) fn run(self, scene: &Scene) {
+        for event in &self.events {
+            match *event {
+                HookEvent::Add(id, entity) => {
+                    if let Some(hook) = &self.hooks[&id].on_add {
+                        hook(scene, entity);
+                    }
+                }
+                HookEvent::Insert(id, entity) => {
+                    if let Some(hook) = &self.hooks[&id].on_insert {
+                        hook(scene, entity);
+                    }
+                }
+                HookEvent::Remove(id, entity, row) => {
+                    if let Some(hook) = &self.hooks[&id].on_remove {
+                        hook(scene, entity, self.removed[&id].get_ptr(row));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// components moved out of an entity to be handed back to the caller,
+// their on_remove hooks see the values before the caller gets them
+pub(crate) struct TakenComponents {
+    entity: Entity,
+    // a column holding the single value of each component, in the order they were asked for
+    values: Vec<(ComponentId, Column, Option<RemoveHook>)>,
+}
+
+impl TakenComponents {
+    pub(crate) fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            values: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, info: &ComponentInfo) {
+        let hook = info.hooks().on_remove.clone();
+        self.values.push((info.id(), Column::new(info), hook));
+    }
+
+    pub(crate) fn column_mut(&mut self, component_id: ComponentId) -> Option<&mut Column> {
+        self.values
+            .iter_mut()
+            .find(|(id, _, _)| *id == component_id)
+            .map(|(_, column, _)| column)
+    }
+
+    pub(crate) fn run_hooks(&self, scene: &Scene) {
+        for (_, column, hook) in &self.values {
+            if let Some(hook) = hook {
+                hook(scene, self.entity, column.get_ptr(0));
+            }
+        }
+    }
+
+    // the values must have been pushed in the `component_ids` order of the bundle
+    pub(crate) unsafe fn into_bundle<B: ComponentBundle>(mut self) -> B {
+        let mut values = self.values.iter_mut();
+        B::from_components(&mut || {
+            let (_, column, _) = values.next().unwrap();
+            let value = column.get_ptr(0);
+            column.swap_remove_forget(0);
+            value
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_utils::{Marker, Tracked};
+
+    #[test]
+    #[should_panic(expected = "not the component it is registered for")]
+    fn on_remove_rejects_another_type() {
+        ComponentHooks::new::<Marker>().on_remove::<Tracked>(|_, _, _| {});
+    }
+}
@@ -1,4 +1,5 @@
 pub mod entity;
+pub mod entity_ref;
 pub mod component;
 pub mod errors;
 pub mod scene;
@@ -9,4 +10,17 @@ pub mod plugins;
 pub mod event;
 pub mod countdown;
 pub mod time;
-pub mod pipeline_executor;
\ No newline at end of file
+pub mod pipeline_executor;
+pub mod storage;
+pub mod hierarchy;
+pub mod relation;
+pub mod prefab;
+pub mod disabled;
+pub mod hooks;
+pub mod change_detection;
+pub mod removal_detection;
+pub mod dynamic;
+pub mod resource;
+pub mod commands;
+#[cfg(test)]
+pub(crate) mod test_utils;
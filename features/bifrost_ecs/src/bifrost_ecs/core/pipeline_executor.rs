@@ -12,6 +12,12 @@ pub struct PipelineExecutor {
     scenes: HashMap<std::any::TypeId, Scene>,
 }
 
+impl Default for PipelineExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PipelineExecutor {
     pub fn new() -> Self {
         Self {
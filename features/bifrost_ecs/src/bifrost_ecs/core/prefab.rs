@@ -0,0 +1,95 @@
+use std::any::TypeId;
+
+use super::{
+    archetype::Archetypes,
+    component::{Component, ComponentBundle, ComponentId},
+    entity::Entity,
+    errors::ComponentError,
+};
+
+trait PrefabComponent: Send + Sync {
+    fn component_type_id(&self) -> TypeId;
+    fn insert(
+        &self,
+        archetypes: &mut Archetypes,
+        entity: Entity,
+    ) -> Result<Option<ComponentId>, ComponentError>;
+}
+
+impl<T: Component + Clone> PrefabComponent for T {
+    fn component_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    // components already given by the overrides are kept, the required ones are left to
+    // `instantiate`
+    fn insert(
+        &self,
+        archetypes: &mut Archetypes,
+        entity: Entity,
+    ) -> Result<Option<ComponentId>, ComponentError> {
+        let component_id = archetypes.register_clone::<T>()?;
+        if archetypes.has_component_id(entity, component_id) {
+            return Ok(None);
+        }
+        archetypes.add_component_without_required(entity, self.clone()).map(Some)
+    }
+}
+
+// a template of components and children that can be spawned many times
+#[derive(Default)]
+pub struct Prefab {
+    components: Vec<Box<dyn PrefabComponent>>,
+    children: Vec<Prefab>,
+}
+
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a second component of the same type replaces the first one
+    pub fn with<T: Component + Clone>(mut self, component: T) -> Self {
+        self.components.retain(|it| (**it).component_type_id() != TypeId::of::<T>());
+        self.components.push(Box::new(component));
+        self
+    }
+
+    pub fn with_child(mut self, child: Prefab) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn children(&self) -> &[Prefab] {
+        &self.children
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+impl Archetypes {
+    pub fn instantiate<B: ComponentBundle>(
+        &mut self,
+        prefab: &Prefab,
+        overrides: B,
+    ) -> Result<Entity, ComponentError> {
+        // required components only fill the gaps once every prefab component is in place
+        let (entity, mut component_ids) = self.spawn_without_required(overrides)?;
+        for component in &prefab.components {
+            component_ids.extend((**component).insert(self, entity)?);
+        }
+        self.insert_required(entity, &component_ids);
+        for child in &prefab.children {
+            let child = self.instantiate(child, ())?;
+            self.set_parent(child, entity)
+                .expect("prefab entity was just spawned");
+        }
+        Ok(entity)
+    }
+}
@@ -1,11 +1,42 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    any::{type_name, TypeId},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
 
-use super::{archetype::Archetype, component::Component, entity::EntityStorage, errors::ComponentError};
+use super::{
+    archetype::{Archetype, ArchetypeId, Archetypes},
+    change_detection::{ComponentTicks, Mut, SystemTicks, Tick},
+    component::{Component, StorageType},
+    entity::Entity,
+    storage::SparseSet,
+};
 
 pub struct QueryFetched<T> {
     result: QueryResult<T>,
 }
 
+impl<T> QueryFetched<T> {
+    pub(crate) fn new(result: QueryResult<T>) -> Self {
+        Self { result }
+    }
+}
+
+impl<T> FromIterator<T> for QueryFetched<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<T> IntoIterator for QueryFetched<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.result.into_iter()
+    }
+}
+
 impl<T> Deref for QueryFetched<T> {
     type Target = QueryResult<T>;
 
@@ -20,123 +51,835 @@ impl<T> DerefMut for QueryFetched<T> {
     }
 }
 
+// fetches one query element out of an archetype, `prepare` looks the storage up once
+// per archetype and `fetch` reads the entity at `row` out of it.
+// `fetch` is unsafe since nothing stops it from handing out a second `Mut` to the same value,
+// the caller makes sure a row is written through at most one item at a time
 pub trait FetchRaw<'a> {
     type RawItem;
-    fn fetch(entity: &'a EntityStorage) -> Result<Self::RawItem, ComponentError>;
-    fn founded(entity: &'a EntityStorage) -> bool {
-        Self::fetch(entity).is_ok()
+    type State;
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State>;
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn fetch(state: &Self::State, entity: Entity, row: usize) -> Option<Self::RawItem>;
+    // every component type the fetch reads or writes
+    fn access(accesses: &mut Vec<Access>);
+    fn founded(archetypes: &'a Archetypes, archetype: &'a Archetype, ticks: SystemTicks) -> bool {
+        Self::prepare(archetypes, archetype, ticks).is_some()
+    }
+    // true when `fetch` can't fail for any row of the archetype the state was prepared for
+    fn is_dense(_state: &Self::State) -> bool {
+        false
     }
+    // false only when the archetype can never be fetched from, whatever is spawned later
+    fn matches_archetype(_archetypes: &'a Archetypes, _archetype: &'a Archetype) -> bool {
+        true
+    }
+}
+
+pub struct Access {
+    type_id: TypeId,
+    name: &'static str,
+    write: bool,
 }
 
+impl Access {
+    pub fn read<T: 'static>() -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            write: false,
+        }
+    }
+
+    pub fn write<T: 'static>() -> Self {
+        Self {
+            write: true,
+            ..Self::read::<T>()
+        }
+    }
+}
+
+// fetches that never write, their items can be held alongside any other reading item,
+// so they are the only ones a shared scene hands out.
+// unsafe to implement since the scene trusts it to keep `Mut` away from shared borrows
+#[allow(clippy::missing_safety_doc)]
+pub unsafe trait ReadOnlyFetch<'a>: FetchRaw<'a> {}
+
+// a query made of read only fetches only
+#[allow(clippy::missing_safety_doc)]
+pub unsafe trait ReadOnlyQuery<'a>: Query<'a> {}
+
+// a query can't hand out a `Mut<T>` next to any other reference to the same `T`
+fn check_access<'a, Q: Query<'a>>() {
+    let mut accesses = Vec::new();
+    Q::access(&mut accesses);
+    for (index, access) in accesses.iter().enumerate() {
+        let conflict = accesses[index + 1..]
+            .iter()
+            .any(|other| other.type_id == access.type_id && (other.write || access.write));
+        if conflict {
+            panic!(
+                "{} is borrowed mutably alongside another borrow in the query {}",
+                access.name,
+                type_name::<Q>()
+            );
+        }
+    }
+}
+
+// where the values of one component type are found for a single archetype
+pub enum StorageFetch<'a, T> {
+    Table(*mut T, *mut ComponentTicks),
+    SparseSet(&'a SparseSet),
+}
+
+impl<'a, T: Component> StorageFetch<'a, T> {
+    pub fn prepare(archetypes: &'a Archetypes, archetype: &'a Archetype) -> Option<Self> {
+        let components = archetypes.components();
+        let id = components.component_id::<T>()?;
+        match components.get_info(id)?.storage_type() {
+            StorageType::Table => {
+                let column = archetype.get_column(id)?;
+                Some(Self::Table(column.as_ptr() as *mut T, column.ticks_ptr()))
+            }
+            StorageType::SparseSet => Some(Self::SparseSet(archetypes.get_sparse_set(id)?)),
+        }
+    }
+
+    pub fn get(&self, entity: Entity, row: usize) -> Option<*mut T> {
+        match self {
+            Self::Table(column, _) => Some(unsafe { column.add(row) }),
+            Self::SparseSet(sparse_set) => sparse_set.get_ptr(entity).map(|it| it as *mut T),
+        }
+    }
+
+    // sparse set components can show up on any entity, table ones only in the archetypes
+    // having their column
+    pub fn may_match(archetypes: &Archetypes, archetype: &Archetype) -> bool {
+        let components = archetypes.components();
+        components.component_id::<T>().is_none_or(|id| {
+            components.get_info(id).unwrap().storage_type() == StorageType::SparseSet
+                || archetype.contains(id)
+        })
+    }
+
+    pub fn is_table(&self) -> bool {
+        matches!(self, Self::Table(..))
+    }
+
+    pub fn get_ticks(&self, entity: Entity, row: usize) -> Option<*mut ComponentTicks> {
+        match self {
+            Self::Table(_, ticks) => Some(unsafe { ticks.add(row) }),
+            Self::SparseSet(sparse_set) => sparse_set.get_ticks_ptr(entity),
+        }
+    }
+}
+
+fn fetch_first<'a, T: FetchRaw<'a>>(
+    archetypes: &'a Archetypes,
+    include_disabled: bool,
+    ticks: SystemTicks,
+) -> Option<T::RawItem> {
+    archetypes
+        .iter()
+        .filter(|archetype| archetypes.is_queryable(archetype, include_disabled))
+        .find_map(|archetype| {
+            let state = T::prepare(archetypes, archetype, ticks)?;
+            archetype
+                .entities()
+                .iter()
+                .enumerate()
+                .find_map(|(row, entity)| unsafe { T::fetch(&state, *entity, row) })
+        })
+}
+
+// unsafe for the same reason as `FetchRaw::fetch`, every call may hand out a new `Mut`
 pub trait Fetch<'a> {
     type RawItem;
     type Item: IntoIterator;
-    fn fetch_single(archetype: &'a Archetype) -> Self::RawItem;
-    fn fetch(archetype: &'a Archetype) -> Self::Item;
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn fetch_single(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self::RawItem;
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn fetch(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self::Item;
 }
 
+// a tuple of fetches, an entity is part of the results only when every one of them is found
 pub trait Query<'a> {
     type Item;
-    fn get_components_in_all_entities(archetype: &'a Archetype) -> QueryFetched<Self::Item>;
+    type State;
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State>;
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn fetch(state: &Self::State, entity: Entity, row: usize) -> Option<Self::Item>;
+    fn access(accesses: &mut Vec<Access>);
+    fn is_dense(state: &Self::State) -> bool;
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool;
+}
+
+// the archetypes a query walks, either every one of them or the ones a `QueryState` matched
+#[derive(Clone)]
+enum ArchetypeCursor<'a> {
+    All(std::slice::Iter<'a, Archetype>),
+    Matched(&'a Archetypes, std::slice::Iter<'a, ArchetypeId>),
+}
+
+impl<'a> Iterator for ArchetypeCursor<'a> {
+    type Item = &'a Archetype;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::All(archetypes) => archetypes.next(),
+            Self::Matched(archetypes, ids) => ids.next().and_then(|id| archetypes.get(*id)),
+        }
+    }
+}
+
+// walks the archetypes lazily, the storage of an archetype is looked up once the iterator
+// gets to it and every entity is only fetched when asked for
+pub struct QueryIter<'a, Q: Query<'a>, F: QueryFilter<'a> = ()> {
+    archetypes: &'a Archetypes,
+    remaining: ArchetypeCursor<'a>,
+    include_disabled: bool,
+    ticks: SystemTicks,
+    current: Option<(&'a Archetype, Q::State, F::State)>,
+    row: usize,
+}
+
+impl<'a, Q: Query<'a>, F: QueryFilter<'a>> QueryIter<'a, Q, F> {
+    pub(crate) fn new(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self {
+        check_access::<Q>();
+        Self {
+            archetypes,
+            remaining: ArchetypeCursor::All(archetypes.iter()),
+            include_disabled,
+            ticks,
+            current: None,
+            row: 0,
+        }
+    }
+
+    pub(crate) fn matched(
+        archetypes: &'a Archetypes,
+        archetype_ids: &'a [ArchetypeId],
+        ticks: SystemTicks,
+    ) -> Self {
+        check_access::<Q>();
+        Self {
+            archetypes,
+            remaining: ArchetypeCursor::Matched(archetypes, archetype_ids.iter()),
+            include_disabled: false,
+            ticks,
+            current: None,
+            row: 0,
+        }
+    }
+
+    fn prepare(&self, archetype: &'a Archetype) -> Option<(Q::State, F::State)> {
+        if !self.archetypes.is_queryable(archetype, self.include_disabled) {
+            return None;
+        }
+        Some((
+            Q::prepare(self.archetypes, archetype, self.ticks)?,
+            F::prepare(self.archetypes, archetype, self.ticks)?,
+        ))
+    }
+}
+
+impl<'a, Q: Query<'a>, F: QueryFilter<'a>> Iterator for QueryIter<'a, Q, F> {
+    type Item = Q::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((archetype, fetch, filter)) = &self.current {
+                while let Some(entity) = archetype.entities().get(self.row).copied() {
+                    let row = self.row;
+                    self.row += 1;
+                    if !F::matches(filter, entity, row) {
+                        continue;
+                    }
+                    // every row is visited once, so its items never overlap
+                    if let Some(item) = unsafe { Q::fetch(fetch, entity, row) } {
+                        return Some(item);
+                    }
+                }
+            }
+            let archetype = self.remaining.next()?;
+            self.current = self
+                .prepare(archetype)
+                .map(|(fetch, filter)| (archetype, fetch, filter));
+            self.row = 0;
+        }
+    }
+
+    // only exact when no archetype left has per entity checks, the rows aren't walked to count
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let current = self.current.as_ref().map(|(archetype, fetch, filter)| {
+            (archetype.len() - self.row, Q::is_dense(fetch) && F::is_dense(filter))
+        });
+        let remaining = self.remaining.clone().filter_map(|archetype| {
+            let (fetch, filter) = self.prepare(archetype)?;
+            Some((archetype.len(), Q::is_dense(&fetch) && F::is_dense(&filter)))
+        });
+        let (upper, exact) = current
+            .into_iter()
+            .chain(remaining)
+            .fold((0, true), |(upper, exact), (len, dense)| (upper + len, exact && dense));
+        (if exact { upper } else { 0 }, Some(upper))
+    }
+}
+
+impl<'a, Q: Query<'a>, F: QueryFilter<'a>> std::iter::FusedIterator for QueryIter<'a, Q, F> {}
+
+// remembers the archetypes a query can match so they aren't searched for again every run,
+// archetypes are never removed so only the ones created since the last run are checked.
+// a state belongs to the scene it was first used with, it panics when used with another one
+pub struct QueryState<Q, F = ()> {
+    archetype_ids: Vec<ArchetypeId>,
+    checked: usize,
+    owner: Option<usize>,
+    marker: PhantomData<fn() -> (Q, F)>,
+}
+
+impl<Q, F> Default for QueryState<Q, F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Q, F> QueryState<Q, F> {
+    pub fn new() -> Self {
+        Self {
+            archetype_ids: Vec::new(),
+            checked: 0,
+            owner: None,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn archetype_ids(&self) -> &[ArchetypeId] {
+        &self.archetype_ids
+    }
+
+    pub fn update<'a>(&mut self, archetypes: &'a Archetypes)
+    where
+        Q: Query<'a>,
+        F: QueryFilter<'a>,
+    {
+        let owner = *self.owner.get_or_insert(archetypes.id());
+        assert_eq!(
+            owner,
+            archetypes.id(),
+            "a QueryState can only be used with the scene it was first used with"
+        );
+        for archetype in archetypes.iter().skip(self.checked) {
+            if !archetypes.is_disabled_archetype(archetype)
+                && Q::matches_archetype(archetypes, archetype)
+                && F::matches_archetype(archetypes, archetype)
+            {
+                self.archetype_ids.push(archetype.id());
+            }
+        }
+        self.checked = archetypes.iter().len();
+    }
+
+    pub(crate) fn iter<'a>(
+        &'a mut self,
+        archetypes: &'a Archetypes,
+        ticks: SystemTicks,
+    ) -> QueryIter<'a, Q, F>
+    where
+        Q: Query<'a>,
+        F: QueryFilter<'a>,
+    {
+        self.update(archetypes);
+        QueryIter::matched(archetypes, &self.archetype_ids, ticks)
+    }
+}
+
+// decides which entities a query visits without fetching anything, `prepare` returns None
+// when no entity of the archetype can match
+pub trait QueryFilter<'a> {
+    type State;
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State>;
+    fn matches(state: &Self::State, entity: Entity, row: usize) -> bool;
+    // true when every entity of the archetype the state was prepared for matches
+    fn is_dense(_state: &Self::State) -> bool {
+        false
+    }
+    // false only when no entity of the archetype can ever match
+    fn matches_archetype(_archetypes: &'a Archetypes, _archetype: &'a Archetype) -> bool {
+        true
+    }
+}
+
+impl<'a> QueryFilter<'a> for () {
+    type State = ();
+
+    fn prepare(_: &'a Archetypes, _: &'a Archetype, _: SystemTicks) -> Option<Self::State> {
+        Some(())
+    }
+
+    fn matches(_: &Self::State, _: Entity, _: usize) -> bool {
+        true
+    }
+
+    fn is_dense(_: &Self::State) -> bool {
+        true
+    }
+}
+
+// entities that got a `T` since the last run of the calling system
+pub struct Added<T>(PhantomData<T>);
+
+// entities whose `T` was added or written since the last run of the calling system
+pub struct Changed<T>(PhantomData<T>);
+
+impl<'a, T: Component> QueryFilter<'a> for Added<T> {
+    type State = (StorageFetch<'a, T>, SystemTicks);
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State> {
+        Some((StorageFetch::prepare(archetypes, archetype)?, ticks))
+    }
+
+    fn matches((storage, ticks): &Self::State, entity: Entity, row: usize) -> bool {
+        storage
+            .get_ticks(entity, row)
+            .is_some_and(|it| unsafe { (*it).is_added(ticks.last_run, ticks.this_run) })
+    }
+
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+        StorageFetch::<T>::may_match(archetypes, archetype)
+    }
+}
+
+impl<'a, T: Component> QueryFilter<'a> for Changed<T> {
+    type State = (StorageFetch<'a, T>, SystemTicks);
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State> {
+        Some((StorageFetch::prepare(archetypes, archetype)?, ticks))
+    }
+
+    fn matches((storage, ticks): &Self::State, entity: Entity, row: usize) -> bool {
+        storage
+            .get_ticks(entity, row)
+            .is_some_and(|it| unsafe { (*it).is_changed(ticks.last_run, ticks.this_run) })
+    }
+
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+        StorageFetch::<T>::may_match(archetypes, archetype)
+    }
+}
+
+// entities having a `T`, nothing is fetched
+pub struct With<T>(PhantomData<T>);
+
+// entities not having a `T`
+pub struct Without<T>(PhantomData<T>);
+
+// at least one filter of the tuple has to match
+pub struct Or<T>(PhantomData<T>);
+
+// entities having at least one of the component types of the tuple
+pub struct AnyOf<T>(PhantomData<T>);
+
+impl<'a, T: Component> QueryFilter<'a> for With<T> {
+    type State = StorageFetch<'a, T>;
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        _: SystemTicks,
+    ) -> Option<Self::State> {
+        StorageFetch::prepare(archetypes, archetype)
+    }
+
+    fn matches(storage: &Self::State, entity: Entity, row: usize) -> bool {
+        storage.get(entity, row).is_some()
+    }
+
+    fn is_dense(storage: &Self::State) -> bool {
+        storage.is_table()
+    }
+
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+        StorageFetch::<T>::may_match(archetypes, archetype)
+    }
+}
+
+// a table component rules out the whole archetype, a sparse one is checked per entity
+impl<'a, T: Component> QueryFilter<'a> for Without<T> {
+    type State = Option<StorageFetch<'a, T>>;
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        _: SystemTicks,
+    ) -> Option<Self::State> {
+        match StorageFetch::prepare(archetypes, archetype) {
+            Some(StorageFetch::Table(..)) => None,
+            storage => Some(storage),
+        }
+    }
+
+    fn matches(storage: &Self::State, entity: Entity, row: usize) -> bool {
+        storage.as_ref().is_none_or(|it| it.get(entity, row).is_none())
+    }
+
+    fn is_dense(storage: &Self::State) -> bool {
+        storage.is_none()
+    }
+
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+        archetypes
+            .components()
+            .component_id::<T>()
+            .is_none_or(|id| !archetype.contains(id))
+    }
+}
+
+// every filter of the tuple has to match
+macro_rules! impl_query_filter_for_tuple {
+    ($($name: ident),*) => {
+        impl<'a, $($name: QueryFilter<'a>),*> QueryFilter<'a> for ($($name,)*) {
+            type State = ($($name::State,)*);
+
+            fn prepare(
+                archetypes: &'a Archetypes,
+                archetype: &'a Archetype,
+                ticks: SystemTicks,
+            ) -> Option<Self::State> {
+                Some(($($name::prepare(archetypes, archetype, ticks)?,)*))
+            }
+
+            #[allow(non_snake_case)]
+            fn matches(state: &Self::State, entity: Entity, row: usize) -> bool {
+                let ($($name,)*) = state;
+                $($name::matches($name, entity, row))&&*
+            }
+
+            #[allow(non_snake_case)]
+            fn is_dense(state: &Self::State) -> bool {
+                let ($($name,)*) = state;
+                $($name::is_dense($name))&&*
+            }
+
+            fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+                $($name::matches_archetype(archetypes, archetype))&&*
+            }
+        }
+    };
 }
+impl_query_filter_for_tuple!(A);
+impl_query_filter_for_tuple!(A, B);
+impl_query_filter_for_tuple!(A, B, C);
+impl_query_filter_for_tuple!(A, B, C, D);
+impl_query_filter_for_tuple!(A, B, C, D, E);
+impl_query_filter_for_tuple!(A, B, C, D, E, F);
+
+// the archetype is skipped only when none of the filters can match in it
+macro_rules! impl_or_filter_for_tuple {
+    ($($name: ident),*) => {
+        impl<'a, $($name: QueryFilter<'a>),*> QueryFilter<'a> for Or<($($name,)*)> {
+            type State = ($(Option<$name::State>,)*);
+
+            #[allow(non_snake_case)]
+            fn prepare(
+                archetypes: &'a Archetypes,
+                archetype: &'a Archetype,
+                ticks: SystemTicks,
+            ) -> Option<Self::State> {
+                let ($($name,)*) = ($($name::prepare(archetypes, archetype, ticks),)*);
+                if $($name.is_some())||* {
+                    Some(($($name,)*))
+                } else {
+                    None
+                }
+            }
+
+            #[allow(non_snake_case)]
+            fn matches(state: &Self::State, entity: Entity, row: usize) -> bool {
+                let ($($name,)*) = state;
+                $($name.as_ref().is_some_and(|it| $name::matches(it, entity, row)))||*
+            }
+
+            #[allow(non_snake_case)]
+            fn is_dense(state: &Self::State) -> bool {
+                let ($($name,)*) = state;
+                $($name.as_ref().is_some_and(|it| $name::is_dense(it)))||*
+            }
+
+            fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+                $($name::matches_archetype(archetypes, archetype))||*
+            }
+        }
+
+        impl<'a, $($name: Component),*> QueryFilter<'a> for AnyOf<($($name,)*)> {
+            type State = <Or<($(With<$name>,)*)> as QueryFilter<'a>>::State;
+
+            fn prepare(
+                archetypes: &'a Archetypes,
+                archetype: &'a Archetype,
+                ticks: SystemTicks,
+            ) -> Option<Self::State> {
+                <Or<($(With<$name>,)*)>>::prepare(archetypes, archetype, ticks)
+            }
+
+            fn matches(state: &Self::State, entity: Entity, row: usize) -> bool {
+                <Or<($(With<$name>,)*)>>::matches(state, entity, row)
+            }
+
+            fn is_dense(state: &Self::State) -> bool {
+                <Or<($(With<$name>,)*)>>::is_dense(state)
+            }
+
+            fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+                <Or<($(With<$name>,)*)>>::matches_archetype(archetypes, archetype)
+            }
+        }
+    };
+}
+impl_or_filter_for_tuple!(A);
+impl_or_filter_for_tuple!(A, B);
+impl_or_filter_for_tuple!(A, B, C);
+impl_or_filter_for_tuple!(A, B, C, D);
+impl_or_filter_for_tuple!(A, B, C, D, E);
+impl_or_filter_for_tuple!(A, B, C, D, E, F);
 
 impl<'a, T: Component> Fetch<'a> for &T {
     type RawItem = &'a T;
     type Item = Vec<(Self::RawItem,)>;
 
-    fn fetch(archetype: &'a Archetype) -> Self::Item {
-        archetype
-            .entities
-            .iter()
-            .filter_map(|entity| entity.get_component::<T>().ok().map(|it| (it,)))
-            .collect()
+    unsafe fn fetch(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self::Item {
+        QueryIter::<(&T,)>::new(archetypes, include_disabled, ticks).collect()
     }
 
-    fn fetch_single(archetype: &'a Archetype) -> Self::RawItem {
-        let a = archetype
-            .entities
-            .iter()
-            .find_map(|entity| entity.get_component::<T>().ok().map(|it| it))
-            .unwrap();
-
-        a
+    unsafe fn fetch_single(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self::RawItem {
+        fetch_first::<&T>(archetypes, include_disabled, ticks).unwrap()
     }
 }
 
 impl<'a, T: Component> Fetch<'a> for &mut T {
-    type RawItem = &'a mut T;
+    type RawItem = Mut<'a, T>;
     type Item = Vec<(Self::RawItem,)>;
 
-    fn fetch(archetype: &'a Archetype) -> Self::Item {
-        archetype
-            .entities
-            .iter()
-            .filter_map(|entity| entity.get_component_mut::<T>().ok().map(|it| (it,)))
-            .collect()
+    unsafe fn fetch(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self::Item {
+        QueryIter::<(&mut T,)>::new(archetypes, include_disabled, ticks).collect()
     }
 
-    fn fetch_single(archetype: &'a Archetype) -> Self::RawItem {
-        archetype
-            .entities
-            .iter()
-            .find_map(|entity| entity.get_component_mut::<T>().ok())
-            .unwrap()
+    unsafe fn fetch_single(
+        archetypes: &'a Archetypes,
+        include_disabled: bool,
+        ticks: SystemTicks,
+    ) -> Self::RawItem {
+        fetch_first::<&mut T>(archetypes, include_disabled, ticks).unwrap()
     }
 }
 
 impl<'a, T: Component> FetchRaw<'a> for &T {
     type RawItem = &'a T;
+    type State = StorageFetch<'a, T>;
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        _: SystemTicks,
+    ) -> Option<Self::State> {
+        StorageFetch::prepare(archetypes, archetype)
+    }
+
+    unsafe fn fetch(state: &Self::State, entity: Entity, row: usize) -> Option<Self::RawItem> {
+        state.get(entity, row).map(|it| unsafe { &*it })
+    }
+
+    fn access(accesses: &mut Vec<Access>) {
+        accesses.push(Access::read::<T>());
+    }
+
+    fn is_dense(state: &Self::State) -> bool {
+        state.is_table()
+    }
 
-    fn fetch(entity: &'a EntityStorage) -> Result<Self::RawItem, ComponentError> {
-        entity.get_component::<T>()
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+        StorageFetch::<T>::may_match(archetypes, archetype)
     }
 }
 
+unsafe impl<'a, T: Component> ReadOnlyFetch<'a> for &T {}
+
+// handed out as `Mut` so only the values actually written are marked as changed
 impl<'a, T: Component> FetchRaw<'a> for &mut T {
-    type RawItem = &'a mut T;
+    type RawItem = Mut<'a, T>;
+    type State = (StorageFetch<'a, T>, Tick);
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State> {
+        Some((StorageFetch::prepare(archetypes, archetype)?, ticks.this_run))
+    }
 
-    fn fetch(entity: &'a EntityStorage) -> Result<Self::RawItem, ComponentError> {
-        entity.get_component_mut::<T>()
+    unsafe fn fetch(
+        (storage, this_run): &Self::State,
+        entity: Entity,
+        row: usize,
+    ) -> Option<Self::RawItem> {
+        let value = storage.get(entity, row)?;
+        let ticks = storage.get_ticks(entity, row)?;
+        Some(unsafe { Mut::new(&mut *value, &mut *ticks, *this_run) })
+    }
+
+    fn access(accesses: &mut Vec<Access>) {
+        accesses.push(Access::write::<T>());
+    }
+
+    fn is_dense((storage, _): &Self::State) -> bool {
+        storage.is_table()
+    }
+
+    fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+        StorageFetch::<T>::may_match(archetypes, archetype)
     }
 }
 
-pub type QueryResult<T> = Vec<T>;
+// never drops the entity from the results, missing components are fetched as None
+impl<'a, T: Component> FetchRaw<'a> for Option<&T> {
+    type RawItem = Option<&'a T>;
+    type State = Option<<&'a T as FetchRaw<'a>>::State>;
 
-impl<'a, A: FetchRaw<'a>> Query<'a> for (A,) {
-    type Item = (A::RawItem,);
-    fn get_components_in_all_entities(archetype: &'a Archetype) -> QueryFetched<Self::Item> {
-        let mut res = Vec::new();
-        for entity in &archetype.entities {
-            if let Ok(a) = A::fetch(entity) {
-                res.push((a,));
-            }
-        }
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State> {
+        Some(<&T>::prepare(archetypes, archetype, ticks))
+    }
+
+    unsafe fn fetch(state: &Self::State, entity: Entity, row: usize) -> Option<Self::RawItem> {
+        Some(state.as_ref().and_then(|it| unsafe { <&T as FetchRaw>::fetch(it, entity, row) }))
+    }
+
+    fn access(accesses: &mut Vec<Access>) {
+        <&T>::access(accesses);
+    }
+
+    fn is_dense(_: &Self::State) -> bool {
+        true
+    }
+}
+
+unsafe impl<'a, T: Component> ReadOnlyFetch<'a> for Option<&T> {}
+
+impl<'a, T: Component> FetchRaw<'a> for Option<&mut T> {
+    type RawItem = Option<Mut<'a, T>>;
+    type State = Option<<&'a mut T as FetchRaw<'a>>::State>;
+
+    fn prepare(
+        archetypes: &'a Archetypes,
+        archetype: &'a Archetype,
+        ticks: SystemTicks,
+    ) -> Option<Self::State> {
+        Some(<&mut T>::prepare(archetypes, archetype, ticks))
+    }
+
+    unsafe fn fetch(state: &Self::State, entity: Entity, row: usize) -> Option<Self::RawItem> {
+        let fetch = |it| unsafe { <&mut T as FetchRaw>::fetch(it, entity, row) };
+        Some(state.as_ref().and_then(fetch))
+    }
+
+    fn access(accesses: &mut Vec<Access>) {
+        <&mut T>::access(accesses);
+    }
 
-        QueryFetched { result: res }
+    fn is_dense(_: &Self::State) -> bool {
+        true
     }
 }
 
+pub type QueryResult<T> = Vec<T>;
+
 macro_rules! impl_query_for_tuple {
     ($($name: ident),*) => {
-        impl<'a, $($name: FetchRaw<'a>),*> Query<'a> for ($($name),*) {
-            type Item = ($($name::RawItem),*);
-            fn get_components_in_all_entities(archetype: &'a Archetype) ->  QueryFetched<Self::Item> {
-                let mut res = Vec::new();
-                for entity in &archetype.entities {
-                    let matches = $($name::founded(entity))&&*;
-                    if matches {
-                        res.push(($($name::fetch(entity).unwrap()),*));
-                    }
-                }
+        impl<'a, $($name: FetchRaw<'a>),*> Query<'a> for ($($name,)*) {
+            type Item = ($($name::RawItem,)*);
+            type State = ($($name::State,)*);
 
-                QueryFetched {
-                    result: res
-                }
+            fn prepare(
+                archetypes: &'a Archetypes,
+                archetype: &'a Archetype,
+                ticks: SystemTicks,
+            ) -> Option<Self::State> {
+                Some(($($name::prepare(archetypes, archetype, ticks)?,)*))
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch(state: &Self::State, entity: Entity, row: usize) -> Option<Self::Item> {
+                let ($($name,)*) = state;
+                Some(($(unsafe { $name::fetch($name, entity, row) }?,)*))
+            }
+
+            fn access(accesses: &mut Vec<Access>) {
+                $($name::access(accesses);)*
+            }
+
+            #[allow(non_snake_case)]
+            fn is_dense(state: &Self::State) -> bool {
+                let ($($name,)*) = state;
+                $($name::is_dense($name))&&*
+            }
+
+            fn matches_archetype(archetypes: &'a Archetypes, archetype: &'a Archetype) -> bool {
+                $($name::matches_archetype(archetypes, archetype))&&*
             }
         }
+
+        unsafe impl<'a, $($name: ReadOnlyFetch<'a>),*> ReadOnlyQuery<'a> for ($($name,)*) {}
     };
 }
-
+impl_query_for_tuple!(A);
 impl_query_for_tuple!(A, B);
 impl_query_for_tuple!(A, B, C);
 impl_query_for_tuple!(A, B, C, D);
@@ -148,3 +891,66 @@ impl_query_for_tuple!(A, B, C, D, E, F, G, H, I);
 impl_query_for_tuple!(A, B, C, D, E, F, G, H, I, J);
 impl_query_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
 impl_query_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Position(u32);
+    struct Velocity(u32);
+
+    #[cfg(not(feature = "blanket_component"))]
+    impl Component for Position {}
+    #[cfg(not(feature = "blanket_component"))]
+    impl Component for Velocity {}
+
+    fn archetypes() -> Archetypes {
+        let mut archetypes = Archetypes::new();
+        archetypes.spawn((Position(1), Velocity(2))).unwrap();
+        archetypes
+    }
+
+    #[test]
+    fn disjoint_access_is_allowed() {
+        let mut archetypes = archetypes();
+        for (mut position, velocity) in archetypes.query_mut::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+        }
+        let items: Vec<_> = archetypes.query::<(&Position, Option<&Position>)>().collect();
+        assert!(matches!(items[..], [(Position(3), Some(Position(3)))]));
+    }
+
+    #[test]
+    fn size_hint_is_exact_only_without_per_entity_checks() {
+        let mut archetypes = archetypes();
+        archetypes.spawn((Position(3),)).unwrap();
+        let mut query = archetypes.query::<(&Position,)>();
+        assert_eq!(query.size_hint(), (2, Some(2)));
+        query.next();
+        assert_eq!(query.size_hint(), (1, Some(1)));
+        let filtered = archetypes.query_filtered::<(&Position,), Added<Position>>(Tick::default());
+        assert_eq!(filtered.size_hint(), (0, Some(2)));
+        assert_eq!(filtered.count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "scene it was first used with")]
+    fn state_is_tied_to_its_scene() {
+        let mut state = QueryState::<(&Position,)>::new();
+        let first = archetypes();
+        assert_eq!(first.query_with_state(&mut state, Tick::default()).count(), 1);
+        archetypes().query_with_state(&mut state, Tick::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed mutably")]
+    fn duplicate_mutable_access_panics() {
+        archetypes().query_mut::<(&mut Position, &mut Position)>();
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed mutably")]
+    fn shared_and_mutable_access_panics() {
+        archetypes().query_mut::<(&Position, &Velocity, Option<&mut Position>)>();
+    }
+}
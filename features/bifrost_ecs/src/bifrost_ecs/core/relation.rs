@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use super::{
+    archetype::Archetypes,
+    component::{Component, ComponentId},
+    entity::{Entity, EntityId},
+    errors::ArchetypeError,
+};
+
+// what happens to the source of a relation when its target is removed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RelationCleanup {
+    RemoveRelation,
+    DespawnSource,
+}
+
+// a kind of link between two entities, for example `struct Likes;` or `struct DockedAt { slot: u32 }`
+pub trait Relation: Component {
+    const CLEANUP: RelationCleanup = RelationCleanup::RemoveRelation;
+}
+
+// every (relation, target) pair of one source entity, only written by the engine
+pub struct Relations<R: Relation> {
+    pairs: Vec<(Entity, R)>,
+}
+
+#[cfg(not(feature = "blanket_component"))]
+impl<R: Relation> Component for Relations<R> {}
+
+impl<R: Relation> Relations<R> {
+    pub fn targets(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.pairs.iter().map(|(target, _)| *target)
+    }
+
+    pub fn get(&self, target: Entity) -> Option<&R> {
+        self.pairs
+            .iter()
+            .find(|(it, _)| *it == target)
+            .map(|(_, relation)| relation)
+    }
+
+    pub fn get_mut(&mut self, target: Entity) -> Option<&mut R> {
+        self.pairs
+            .iter_mut()
+            .find(|(it, _)| *it == target)
+            .map(|(_, relation)| relation)
+    }
+
+    pub fn contains(&self, target: Entity) -> bool {
+        self.get(target).is_some()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, (Entity, R)> {
+        self.pairs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RelationKind {
+    cleanup: RelationCleanup,
+    targets: fn(&Archetypes, Entity) -> Vec<Entity>,
+    remove: fn(&mut Archetypes, Entity, Entity) -> Result<(), ArchetypeError>,
+}
+
+// reverse lookup from a target to the sources pointing at it, keyed by the `Relations<R>` component id
+#[derive(Default)]
+pub struct RelationIndex {
+    kinds: HashMap<ComponentId, RelationKind>,
+    sources: HashMap<Entity, Vec<(ComponentId, Entity)>>,
+}
+
+impl RelationIndex {
+    pub fn is_relation(&self, component_id: ComponentId) -> bool {
+        self.kinds.contains_key(&component_id)
+    }
+}
+
+fn relation_targets<R: Relation>(archetypes: &Archetypes, source: Entity) -> Vec<Entity> {
+    archetypes
+        .get_entity(source)
+        .ok()
+        .and_then(|entity| entity.get_component::<Relations<R>>().ok())
+        .map(|relations| relations.targets().collect())
+        .unwrap_or_default()
+}
+
+fn remove_relation<R: Relation>(
+    archetypes: &mut Archetypes,
+    source: Entity,
+    target: Entity,
+) -> Result<(), ArchetypeError> {
+    archetypes.remove_relation::<R>(source, target)
+}
+
+impl Archetypes {
+    pub fn add_relation<R: Relation>(
+        &mut self,
+        source: EntityId,
+        target: EntityId,
+        relation: R,
+    ) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.get_location(source)?;
+        self.get_location(target)?;
+        let relations = self
+            .get_entity(source)?
+            .get_component_mut::<Relations<R>>()
+            .ok();
+        match relations {
+            Some(mut relations) => match relations.get_mut(target) {
+                Some(current) => {
+                    *current = relation;
+                    return Ok(());
+                }
+                None => relations.pairs.push((target, relation)),
+            },
+            None => self.add_component_to_entity(
+                source,
+                Relations {
+                    pairs: vec![(target, relation)],
+                },
+            )?,
+        }
+
+        let kind_id = self.components.register::<Relations<R>>()?;
+        self.relations.kinds.entry(kind_id).or_insert(RelationKind {
+            cleanup: R::CLEANUP,
+            targets: relation_targets::<R>,
+            remove: remove_relation::<R>,
+        });
+        let sources = self.relations.sources.entry(target).or_default();
+        if !sources.contains(&(kind_id, source)) {
+            sources.push((kind_id, source));
+        }
+        Ok(())
+    }
+
+    pub fn remove_relation<R: Relation>(
+        &mut self,
+        source: EntityId,
+        target: EntityId,
+    ) -> Result<(), ArchetypeError> {
+        self.flush();
+        self.get_location(source)?;
+        let Some(mut relations) = self
+            .get_entity(source)?
+            .get_component_mut::<Relations<R>>()
+            .ok()
+        else {
+            return Ok(());
+        };
+        relations.pairs.retain(|(it, _)| *it != target);
+        if relations.is_empty() {
+            self.remove_component_from_entity::<Relations<R>>(source)?;
+        }
+        if let Some(kind_id) = self.components.component_id::<Relations<R>>() {
+            if let Some(sources) = self.relations.sources.get_mut(&target) {
+                sources.retain(|it| *it != (kind_id, source));
+                if sources.is_empty() {
+                    self.relations.sources.remove(&target);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_relation<R: Relation>(&self, source: EntityId, target: EntityId) -> bool {
+        self.get_entity(source)
+            .ok()
+            .and_then(|entity| entity.get_component::<Relations<R>>().ok())
+            .is_some_and(|relations| relations.contains(target))
+    }
+
+    pub fn relation_targets<R: Relation>(&self, source: EntityId) -> Vec<Entity> {
+        relation_targets::<R>(self, source)
+    }
+
+    // every entity that has the relation `R` pointing at `target`
+    pub fn relation_sources<R: Relation>(&self, target: EntityId) -> Vec<Entity> {
+        let Some(kind_id) = self.components.component_id::<Relations<R>>() else {
+            return Vec::new();
+        };
+        self.relations
+            .sources
+            .get(&target)
+            .map(|sources| {
+                sources
+                    .iter()
+                    .filter(|(id, source)| {
+                        *id == kind_id && self.has_relation::<R>(*source, target)
+                    })
+                    .map(|(_, source)| *source)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // drops the relations of the entity and applies the cleanup rule to everything pointing at it
+    pub(crate) fn detach_relations(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        let kinds: Vec<(ComponentId, RelationKind)> = self
+            .relations
+            .kinds
+            .iter()
+            .map(|(id, kind)| (*id, *kind))
+            .collect();
+        for (kind_id, kind) in &kinds {
+            for target in (kind.targets)(self, entity_id) {
+                if let Some(sources) = self.relations.sources.get_mut(&target) {
+                    sources.retain(|it| *it != (*kind_id, entity_id));
+                    if sources.is_empty() {
+                        self.relations.sources.remove(&target);
+                    }
+                }
+            }
+        }
+
+        // the index is not told when `Relations<R>` is taken or removed as a plain component,
+        // so only sources that still hold the pair are cleaned up
+        let sources = self.relations.sources.remove(&entity_id).unwrap_or_default();
+        for (kind_id, source) in sources {
+            let kind = self.relations.kinds[&kind_id];
+            if !self.is_alive(source) || !(kind.targets)(self, source).contains(&entity_id) {
+                continue;
+            }
+            match kind.cleanup {
+                RelationCleanup::RemoveRelation => (kind.remove)(self, source, entity_id)?,
+                RelationCleanup::DespawnSource => self.remove_entity(source)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_utils::Marker;
+
+    struct Guards;
+
+    #[cfg(not(feature = "blanket_component"))]
+    impl Component for Guards {}
+
+    impl Relation for Guards {
+        const CLEANUP: RelationCleanup = RelationCleanup::DespawnSource;
+    }
+
+    #[test]
+    fn removed_relations_are_not_cleaned_up() {
+        let mut archetypes = Archetypes::new();
+        let guard = archetypes.spawn((Marker,)).unwrap();
+        let guarded = archetypes.spawn((Marker,)).unwrap();
+        archetypes.add_relation(guard, guarded, Guards).unwrap();
+        archetypes.remove_component_from_entity::<Relations<Guards>>(guard).unwrap();
+        assert!(!archetypes.has_relation::<Guards>(guard, guarded));
+        assert!(archetypes.relation_sources::<Guards>(guarded).is_empty());
+
+        archetypes.remove_entity(guarded).unwrap();
+        assert!(archetypes.is_alive(guard));
+    }
+}
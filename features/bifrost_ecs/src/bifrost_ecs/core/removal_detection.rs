@@ -0,0 +1,83 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use super::{
+    change_detection::{SystemTicks, Tick},
+    component::{Component, ComponentId},
+    entity::Entity,
+};
+
+// entities that lost a component, kept for the current and the previous frame
+// so every system gets to see them once whatever its position in the frame
+#[derive(Default)]
+pub struct RemovedComponentEvents {
+    current: HashMap<ComponentId, Vec<(Entity, Tick)>>,
+    previous: HashMap<ComponentId, Vec<(Entity, Tick)>>,
+}
+
+impl RemovedComponentEvents {
+    pub(crate) fn record(&mut self, component_id: ComponentId, entity: Entity, tick: Tick) {
+        self.current
+            .entry(component_id)
+            .or_default()
+            .push((entity, tick));
+    }
+
+    pub(crate) fn check_change_ticks(&mut self, this_run: Tick) {
+        for events in self.current.values_mut().chain(self.previous.values_mut()) {
+            for (_, tick) in events {
+                tick.check_tick(this_run);
+            }
+        }
+    }
+
+    // called once per frame, drops what was removed two frames ago
+    pub(crate) fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn get(&self, component_id: ComponentId, ticks: SystemTicks) -> Vec<Entity> {
+        [&self.previous, &self.current]
+            .into_iter()
+            .filter_map(|events| events.get(&component_id))
+            .flatten()
+            .filter(|(_, tick)| tick.is_newer_than(ticks.last_run, ticks.this_run))
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+}
+
+// the entities that lost a `T` since the last run of the calling system
+pub struct RemovedComponents<T: Component> {
+    entities: Vec<Entity>,
+    marker: PhantomData<T>,
+}
+
+impl<T: Component> RemovedComponents<T> {
+    pub(crate) fn new(entities: Vec<Entity>) -> Self {
+        Self {
+            entities,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Entity> {
+        self.entities.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl<T: Component> IntoIterator for RemovedComponents<T> {
+    type Item = Entity;
+    type IntoIter = std::vec::IntoIter<Entity>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entities.into_iter()
+    }
+}
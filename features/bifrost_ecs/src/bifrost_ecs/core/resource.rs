@@ -0,0 +1,135 @@
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+};
+
+use super::{
+    archetype::Archetypes,
+    change_detection::{ComponentTicks, Mut, Tick},
+};
+
+// a global singleton of the scene, stored once by type instead of on an entity
+pub trait Resource: 'static + Send + Sync {}
+
+impl<T: 'static + Send + Sync> Resource for T {}
+
+struct ResourceData {
+    value: UnsafeCell<Box<dyn Any + Send + Sync>>,
+    ticks: UnsafeCell<ComponentTicks>,
+}
+
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, ResourceData>,
+}
+
+// resources are written through shared references like the component columns
+unsafe impl Sync for Resources {}
+
+impl Resources {
+    fn get<T: Resource>(&self) -> Option<&ResourceData> {
+        self.resources.get(&TypeId::of::<T>())
+    }
+
+    pub(crate) fn check_change_ticks(&mut self, this_run: Tick) {
+        for resource in self.resources.values_mut() {
+            resource.ticks.get_mut().check_ticks(this_run);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+// values that can't leave the thread they were made on, like ffi contexts or `Rc` caches.
+// the store is not Send itself, so it stays with the scene on the main thread and is only
+// handed to the systems pinned there
+#[derive(Default)]
+pub struct NonSendResources {
+    resources: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl NonSendResources {
+    pub fn insert<T: 'static>(&mut self, value: T) {
+        self.resources.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.resources.get(&TypeId::of::<T>())?.downcast_ref::<T>()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.resources.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>()
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        let value = self.resources.remove(&TypeId::of::<T>())?;
+        value.downcast::<T>().ok().map(|value| *value)
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+impl Archetypes {
+    // replaces the resource of the same type if there is one
+    pub fn insert_resource<T: Resource>(&mut self, value: T) {
+        let tick = self.change_tick();
+        match self.resources.resources.get_mut(&TypeId::of::<T>()) {
+            Some(resource) => {
+                *resource.value.get_mut() = Box::new(value);
+                resource.ticks.get_mut().changed = tick;
+            }
+            None => {
+                let resource = ResourceData {
+                    value: UnsafeCell::new(Box::new(value)),
+                    ticks: UnsafeCell::new(ComponentTicks::new(tick)),
+                };
+                self.resources.resources.insert(TypeId::of::<T>(), resource);
+            }
+        }
+    }
+
+    pub fn resource<T: Resource>(&self) -> Option<&T> {
+        let resource = self.resources.get::<T>()?;
+        unsafe { (*resource.value.get()).downcast_ref::<T>() }
+    }
+
+    // the resource is only marked as changed once it is written through the returned `Mut`,
+    // the scene hands it out only while borrowed mutably
+    pub(crate) fn resource_mut<T: Resource>(&self) -> Option<Mut<'_, T>> {
+        let resource = self.resources.get::<T>()?;
+        let value = unsafe { (*resource.value.get()).downcast_mut::<T>()? };
+        let ticks = unsafe { &mut *resource.ticks.get() };
+        Some(Mut::new(value, ticks, self.change_tick()))
+    }
+
+    pub fn resource_ticks<T: Resource>(&self) -> Option<ComponentTicks> {
+        let resource = self.resources.get::<T>()?;
+        Some(unsafe { *resource.ticks.get() })
+    }
+
+    pub fn remove_resource<T: Resource>(&mut self) -> Option<T> {
+        let resource = self.resources.resources.remove(&TypeId::of::<T>())?;
+        resource.value.into_inner().downcast::<T>().ok().map(|value| *value)
+    }
+
+    pub fn contains_resource<T: Resource>(&self) -> bool {
+        self.resources.get::<T>().is_some()
+    }
+}
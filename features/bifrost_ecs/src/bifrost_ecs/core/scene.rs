@@ -1,24 +1,43 @@
 use std::{
-    any::TypeId,
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     sync::{Arc, Mutex, RwLock},
 };
 
 use super::{
-    archetype::Archetype,
-    component::{Component, ComponentBundle},
+    archetype::Archetypes,
+    change_detection::{Mut, Tick},
+    commands::{Command, Commands},
+    component::{
+        Component, ComponentBundle, ComponentDescriptor, ComponentId, Components, StorageType,
+    },
+    dynamic::DynamicItem,
     countdown::Timers,
-    entity::{Entity, EntityId, EntityStorage, FetchItem},
-    errors::ArchetypeError,
+    entity::{Entity, EntityId, FetchItem},
+    entity_ref::{EntityMut, EntityRef},
+    errors::{ArchetypeError, ComponentError},
     event::{EventComponent, EventStorage},
+    hooks::ComponentHooks,
     lifetime_system_exec::LifetimeSystemExec,
     plugins::Plugin,
-    query::{Fetch, Query, QueryFetched},
+    prefab::Prefab,
+    query::{
+        Fetch, Query, QueryFetched, QueryFilter, QueryIter, QueryState, ReadOnlyFetch,
+        ReadOnlyQuery,
+    },
+    relation::Relation,
+    removal_detection::RemovedComponents,
+    resource::{NonSendResources, Resource},
     time::Time,
 };
 
 pub type SystemFunc = (Box<dyn Fn(&Scene)>, LifetimeSystemExec);
 pub type MutSystemFunc = (Box<dyn FnMut(&mut Scene)>, LifetimeSystemExec);
+// every system is stored with the tick of its last run
+pub(crate) type Systems = HashMap<LifetimeSystemExec, Vec<(Box<dyn Fn(&Scene)>, Tick)>>;
+pub(crate) type MutSystems = HashMap<LifetimeSystemExec, Vec<(Box<dyn FnMut(&mut Scene)>, Tick)>>;
+pub type MainThreadSystem = Box<dyn FnMut(&mut Scene, &mut NonSendResources)>;
+pub type MainThreadSystemFunc = (MainThreadSystem, LifetimeSystemExec);
+pub(crate) type MainThreadSystems = HashMap<LifetimeSystemExec, Vec<(MainThreadSystem, Tick)>>;
 #[macro_export]
 macro_rules! system {
     ($(($func_name: ident, $lifetime_system_exec: expr)),*) => {
@@ -43,19 +62,46 @@ macro_rules! system_mut {
         }
     };
 }
+#[macro_export]
+macro_rules! system_main_thread {
+    ($(($func_name: ident, $lifetime_system_exec: expr)),*) => {
+        {
+
+            let v: Vec<$crate::core::scene::MainThreadSystemFunc> = vec![
+                $((Box::new($func_name),$lifetime_system_exec),)*
+            ];
+            v
+        }
+    };
+}
 
 pub struct Scene {
-    pub(crate) archetype: Arc<RwLock<Archetype>>,
-    pub(crate) systems: Arc<Mutex<HashMap<LifetimeSystemExec, Vec<Box<dyn Fn(&Scene)>>>>>,
-    pub(crate) systems_mut:
-        Arc<Mutex<HashMap<LifetimeSystemExec, Vec<Box<dyn FnMut(&mut Scene)>>>>>,
+    pub(crate) archetypes: Arc<RwLock<Archetypes>>,
+    pub(crate) systems: Arc<Mutex<Systems>>,
+    pub(crate) systems_mut: Arc<Mutex<MutSystems>>,
+    // run one after another on the thread owning the scene, never alongside other systems
+    pub(crate) systems_main_thread: Arc<Mutex<MainThreadSystems>>,
+    // only reachable through the parameter of the main thread systems, they are inserted
+    // there too, usually by an OnBegin one
+    non_send: NonSendResources,
+    // applied at the next `flush`
+    pub(crate) commands: Mutex<Vec<Command>>,
     pub events: Arc<RwLock<EventStorage>>,
     pub countdowns: Arc<RwLock<Timers>>,
     is_running: bool,
-    unique_instances: HashSet<TypeId>,
+    // last run of the system being run, `Added` and `Changed` compare against it
+    last_run: Tick,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scene {
+    // systems are not Send, the Arc only lets the scene run them while borrowed mutably
+    #[allow(clippy::arc_with_non_send_sync)]
     pub fn new() -> Self {
         let mut systems = HashMap::new();
         systems.insert(LifetimeSystemExec::OnBegin, Vec::new());
@@ -67,92 +113,490 @@ impl Scene {
         systems_mut.insert(LifetimeSystemExec::OnUpdate, Vec::new());
         systems_mut.insert(LifetimeSystemExec::OnFinish, Vec::new());
 
+        let mut systems_main_thread = HashMap::new();
+        systems_main_thread.insert(LifetimeSystemExec::OnBegin, Vec::new());
+        systems_main_thread.insert(LifetimeSystemExec::OnUpdate, Vec::new());
+        systems_main_thread.insert(LifetimeSystemExec::OnFinish, Vec::new());
+
         let mut scene = Self {
-            archetype: Arc::new(RwLock::new(Archetype::new())),
+            archetypes: Arc::new(RwLock::new(Archetypes::new())),
             is_running: false,
             systems: Arc::new(Mutex::new(systems)),
             systems_mut: Arc::new(Mutex::new(systems_mut)),
+            systems_main_thread: Arc::new(Mutex::new(systems_main_thread)),
+            non_send: NonSendResources::default(),
+            commands: Mutex::new(Vec::new()),
             // window_container: Window::new("Prometheus", 800, 600),
-            unique_instances: HashSet::new(),
+            last_run: Tick::default(),
             events: Arc::new(RwLock::new(EventStorage::new())),
             countdowns: Arc::new(RwLock::new(Timers::new())),
         };
 
-        // resources
-        scene.spawn((
-            Time::new(),
-        ));
-
-        let mut unique_instances = HashSet::new();
-        unique_instances.insert(TypeId::of::<Time>());
-        scene.unique_instances = unique_instances;
+        scene.insert_resource(Time::new());
 
         scene
     }
 
-    pub fn query_single<'a, T: Fetch<'a>>(&'a self) -> T::RawItem {
-        let archetype_arc = self.archetype.clone();
-        let mut read = archetype_arc.try_read().unwrap();
-        let archetype = read.inner();
-        let archetype = unsafe { std::mem::transmute::<&Archetype, &'a Archetype>(archetype) };
-        archetype.query_single::<T>()
+    pub(crate) fn read_archetypes<'a>(&'a self) -> &'a Archetypes {
+        let archetypes_arc = self.archetypes.clone();
+        let mut read = archetypes_arc.try_read().unwrap();
+        let archetypes = read.inner();
+        unsafe { std::mem::transmute::<&Archetypes, &'a Archetypes>(archetypes) }
     }
 
-    pub fn query<'a, T: Query<'a>>(&'a self) -> QueryFetched<T::Item> {
-        let archetype_arc = self.archetype.clone();
-        let mut read = archetype_arc.try_read().unwrap();
-        let archetype = read.inner();
-        let archetype = unsafe { std::mem::transmute::<&Archetype, &'a Archetype>(archetype) };
+    // every structural change goes through here, its hooks run once the lock is released.
+    // taking the scene mutably keeps the references handed out by `read_archetypes` from
+    // outliving a change, commands queue the changes made from a shared scene
+    pub(crate) fn write_archetypes<R>(&mut self, write: impl FnOnce(&mut Archetypes) -> R) -> R {
+        let result = write(&mut self.archetypes.try_write().unwrap());
+        self.run_hooks();
+        result
+    }
 
-        // Then you can call the `run` method on `tst` like this:
-        archetype.query::<T>()
+    fn run_hooks(&self) {
+        loop {
+            let pending = {
+                let mut archetypes = self.archetypes.try_write().unwrap();
+                let archetypes = &mut *archetypes;
+                archetypes.hooks.take_pending(&archetypes.components)
+            };
+            match pending {
+                Some(pending) => pending.run(self),
+                None => break,
+            }
+        }
     }
 
-    pub fn spawn(&self, cb: impl ComponentBundle) -> &Self {
-        let archetype_arc = self.archetype.clone();
-        let mut archetype = archetype_arc.try_write().unwrap();
-        let mut e = EntityStorage::new(archetype.len() as u32);
-        e.add_components(&self.unique_instances, cb);
-        e.add_component(Entity(e.id)).unwrap();
-        archetype.spawn(e);
+    // only read only queries run on a shared scene, like in `Fn(&Scene)` systems,
+    // the `_mut` ones hand out `Mut` and need the scene mutably so nothing else can borrow
+    // the values they write, writes from a shared scene go through `commands`
+    pub fn query_single<'a, T: Fetch<'a> + ReadOnlyFetch<'a>>(
+        &'a self,
+    ) -> <T as Fetch<'a>>::RawItem {
+        self.read_archetypes().query_single::<T>()
+    }
 
-        self
+    pub fn query_single_mut<'a, T: Fetch<'a>>(&'a mut self) -> T::RawItem {
+        let archetypes = self.read_archetypes();
+        unsafe { <T>::fetch_single(archetypes, false, archetypes.system_ticks(Tick::default())) }
+    }
+
+    pub fn query<'a, T: ReadOnlyQuery<'a>>(&'a self) -> QueryIter<'a, T> {
+        self.read_archetypes().query::<T>()
+    }
+
+    pub fn query_mut<'a, T: Query<'a>>(&'a mut self) -> QueryIter<'a, T> {
+        unsafe { self.read_archetypes().query_unchecked(false, Tick::default()) }
+    }
+
+    // `Added` and `Changed` are relative to the last run of the system calling it,
+    // outside of a system everything counts as added and changed
+    pub fn query_filtered<'a, T: ReadOnlyQuery<'a>, F: QueryFilter<'a>>(
+        &'a self,
+    ) -> QueryIter<'a, T, F> {
+        self.read_archetypes().query_filtered::<T, F>(self.last_run)
+    }
+
+    pub fn query_filtered_mut<'a, T: Query<'a>, F: QueryFilter<'a>>(
+        &'a mut self,
+    ) -> QueryIter<'a, T, F> {
+        unsafe { self.read_archetypes().query_unchecked(false, self.last_run) }
     }
 
-    pub fn spawn_batch(&mut self, cbs: Vec<impl ComponentBundle>) -> &mut Self {
-        for cb in cbs {
-            self.spawn(cb);
+    // keep the state around between runs, only the archetypes created since its last use are
+    // checked against the query
+    pub fn query_with_state<'a, T: ReadOnlyQuery<'a>, F: QueryFilter<'a>>(
+        &'a self,
+        state: &'a mut QueryState<T, F>,
+    ) -> QueryIter<'a, T, F> {
+        self.read_archetypes().query_with_state(state, self.last_run)
+    }
+
+    pub fn query_with_state_mut<'a, T: Query<'a>, F: QueryFilter<'a>>(
+        &'a mut self,
+        state: &'a mut QueryState<T, F>,
+    ) -> QueryIter<'a, T, F> {
+        unsafe { self.read_archetypes().query_with_state_unchecked(state, self.last_run) }
+    }
+
+    // same as `query_single` but disabled entities are visited too
+    pub fn query_single_with_disabled<'a, T: Fetch<'a> + ReadOnlyFetch<'a>>(
+        &'a self,
+    ) -> <T as Fetch<'a>>::RawItem {
+        self.read_archetypes().query_single_with_disabled::<T>()
+    }
+
+    pub fn query_single_with_disabled_mut<'a, T: Fetch<'a>>(&'a mut self) -> T::RawItem {
+        let archetypes = self.read_archetypes();
+        unsafe { <T>::fetch_single(archetypes, true, archetypes.system_ticks(Tick::default())) }
+    }
+
+    pub fn query_with_disabled<'a, T: ReadOnlyQuery<'a>>(&'a self) -> QueryIter<'a, T> {
+        self.read_archetypes().query_with_disabled::<T>()
+    }
+
+    pub fn query_with_disabled_mut<'a, T: Query<'a>>(&'a mut self) -> QueryIter<'a, T> {
+        unsafe { self.read_archetypes().query_unchecked(true, Tick::default()) }
+    }
+
+    pub fn spawn(&mut self, cb: impl ComponentBundle) -> Entity {
+        self.try_spawn(cb).unwrap()
+    }
+
+    // fails instead of panicking when the bundle holds the same component type twice
+    pub fn try_spawn(&mut self, cb: impl ComponentBundle) -> Result<Entity, ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.spawn(cb))
+    }
+
+    // hands out an id right away without locking the archetypes for writing,
+    // the entity is materialized at the next `flush`, which runs after every system
+    pub fn reserve_entity(&self) -> Entity {
+        self.read_archetypes().reserve_entity()
+    }
+
+    // materializes the reserved entities, then applies the queued commands,
+    // including the ones queued while applying them
+    pub fn flush(&mut self) {
+        self.write_archetypes(|archetypes| archetypes.flush());
+        loop {
+            let commands = std::mem::take(self.commands.get_mut().unwrap());
+            if commands.is_empty() {
+                break;
+            }
+            for command in commands {
+                command(self);
+            }
         }
-        self
     }
 
-    pub fn remove_entity(&mut self, entity_id: EntityId) {
-        self.archetype
-            .clone()
-            .try_write()
-            .unwrap()
-            .remove_entity(entity_id);
+    // structural changes need the scene mutably, this queues them from a shared one instead
+    pub fn commands(&self) -> Commands<'_> {
+        Commands::new(self)
     }
 
-    pub fn remove_component_from_entity<T: Component>(&mut self, entity_id: EntityId) {
-        self.archetype
-            .clone()
-            .try_write()
+    pub fn spawn_empty(&mut self) -> EntityMut<'_> {
+        let entity = self.spawn(());
+        EntityMut::new(self, entity)
+    }
+
+    pub fn spawn_batch(&mut self, cbs: Vec<impl ComponentBundle>) -> Vec<Entity> {
+        cbs.into_iter().map(|cb| self.spawn(cb)).collect()
+    }
+
+    pub fn instantiate(&mut self, prefab: &Prefab) -> Entity {
+        self.instantiate_with(prefab, ())
+    }
+
+    // the overrides take the place of the prefab components of the same type
+    pub fn instantiate_with(&mut self, prefab: &Prefab, overrides: impl ComponentBundle) -> Entity {
+        self.write_archetypes(|archetypes| archetypes.instantiate(prefab, overrides))
             .unwrap()
-            .remove_component_from_entity::<T>(entity_id);
     }
 
-    pub fn add_component_to_entity<T: Component>(
+    pub fn clone_entity(&mut self, entity_id: EntityId) -> Result<Entity, ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.clone_entity(entity_id))
+    }
+
+    pub fn entity(&self, entity_id: EntityId) -> Result<EntityRef<'_>, ArchetypeError> {
+        self.read_archetypes().get_location(entity_id)?;
+        Ok(EntityRef::new(self, entity_id))
+    }
+
+    pub fn entity_mut(&mut self, entity_id: EntityId) -> Result<EntityMut<'_>, ArchetypeError> {
+        self.read_archetypes().get_location(entity_id)?;
+        Ok(EntityMut::new(self, entity_id))
+    }
+
+    pub fn remove_entity(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.remove_entity(entity_id))
+    }
+
+    pub fn despawn_recursive(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.remove_entity_recursive(entity_id))
+    }
+
+    pub fn add_child(&mut self, parent: EntityId, child: EntityId) -> Result<(), ArchetypeError> {
+        self.set_parent(child, parent)
+    }
+
+    pub fn remove_child(&mut self, parent: EntityId, child: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| {
+            if archetypes.parent_of(child) != Some(parent) {
+                return Ok(());
+            }
+            archetypes.remove_parent(child)
+        })
+    }
+
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.set_parent(child, parent))
+    }
+
+    pub fn remove_parent(&mut self, child: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.remove_parent(child))
+    }
+
+    pub fn ancestors(&self, entity_id: EntityId) -> Vec<Entity> {
+        self.read_archetypes().ancestors(entity_id)
+    }
+
+    pub fn descendants(&self, entity_id: EntityId) -> Vec<Entity> {
+        self.read_archetypes().descendants(entity_id)
+    }
+
+    pub fn is_disabled(&self, entity_id: EntityId) -> bool {
+        self.read_archetypes().is_disabled(entity_id)
+    }
+
+    pub fn disable(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.disable(entity_id))
+    }
+
+    pub fn enable(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.enable(entity_id))
+    }
+
+    pub fn disable_recursive(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.disable_recursive(entity_id))
+    }
+
+    pub fn enable_recursive(&mut self, entity_id: EntityId) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.enable_recursive(entity_id))
+    }
+
+    pub fn add_relation<R: Relation>(
+        &mut self,
+        source: EntityId,
+        target: EntityId,
+        relation: R,
+    ) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.add_relation(source, target, relation))
+    }
+
+    pub fn remove_relation<R: Relation>(
+        &mut self,
+        source: EntityId,
+        target: EntityId,
+    ) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.remove_relation::<R>(source, target))
+    }
+
+    pub fn has_relation<R: Relation>(&self, source: EntityId, target: EntityId) -> bool {
+        self.read_archetypes().has_relation::<R>(source, target)
+    }
+
+    pub fn relation_targets<R: Relation>(&self, source: EntityId) -> Vec<Entity> {
+        self.read_archetypes().relation_targets::<R>(source)
+    }
+
+    pub fn relation_sources<R: Relation>(&self, target: EntityId) -> Vec<Entity> {
+        self.read_archetypes().relation_sources::<R>(target)
+    }
+
+    pub fn remove_component_from_entity<T: Component>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.remove_component_from_entity::<T>(entity_id))
+    }
+
+    // the value is handed back instead of being dropped, its on_remove hook still runs
+    pub fn take<T: Component>(&mut self, entity_id: EntityId) -> Result<T, ArchetypeError> {
+        self.remove_bundle::<(T,)>(entity_id).map(|(component,)| component)
+    }
+
+    // all or nothing, the entity is left untouched if it misses any of the components
+    pub fn remove_bundle<B: ComponentBundle>(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<B, ArchetypeError> {
+        let taken = self.write_archetypes(|archetypes| {
+            let mut component_ids = Vec::new();
+            B::component_ids(&mut archetypes.components, &mut component_ids)?;
+            archetypes.take_components(entity_id, &component_ids)
+        })?;
+        taken.run_hooks(self);
+        self.run_hooks();
+        Ok(unsafe { taken.into_bundle::<B>() })
+    }
+
+    // returns the value the entity had before, on_insert runs either way
+    pub fn insert_or_replace<T: Component>(
         &mut self,
         entity_id: EntityId,
         component: T,
+    ) -> Result<Option<T>, ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.insert_or_replace(entity_id, component))
+    }
+
+    // the entities that lost a `T` since the last run of the calling system, at most one frame back
+    pub fn removed_components<T: Component>(&self) -> RemovedComponents<T> {
+        let archetypes = self.read_archetypes();
+        let entities = match archetypes.components().component_id::<T>() {
+            Some(component_id) => archetypes
+                .removed
+                .get(component_id, archetypes.system_ticks(self.last_run)),
+            None => Vec::new(),
+        };
+        RemovedComponents::new(entities)
+    }
+
+    // resources are global singletons of the scene, they don't live on any entity
+    pub fn insert_resource<T: Resource>(&mut self, value: T) {
+        self.write_archetypes(|archetypes| archetypes.insert_resource(value));
+    }
+
+    pub fn resource<T: Resource>(&self) -> Option<&T> {
+        self.read_archetypes().resource::<T>()
+    }
+
+    // takes the scene mutably so only one `Mut` of a resource is alive at a time
+    pub fn resource_mut<T: Resource>(&mut self) -> Option<Mut<'_, T>> {
+        self.read_archetypes().resource_mut::<T>()
+    }
+
+    pub fn remove_resource<T: Resource>(&mut self) -> Option<T> {
+        self.write_archetypes(|archetypes| archetypes.remove_resource::<T>())
+    }
+
+    pub fn contains_resource<T: Resource>(&self) -> bool {
+        self.read_archetypes().contains_resource::<T>()
+    }
+
+    // inserted or written since the last run of the calling system
+    pub fn resource_changed<T: Resource>(&self) -> bool {
+        let archetypes = self.read_archetypes();
+        let ticks = archetypes.system_ticks(self.last_run);
+        archetypes
+            .resource_ticks::<T>()
+            .is_some_and(|it| it.is_changed(ticks.last_run, ticks.this_run))
+    }
+
+    pub fn is_alive(&self, entity_id: EntityId) -> bool {
+        self.archetypes.try_read().unwrap().is_alive(entity_id)
+    }
+
+    pub fn register_component<T: Component>(
+        &mut self,
+        storage_type: StorageType,
+    ) -> Result<(), ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.register_component::<T>(storage_type))?;
+        Ok(())
+    }
+
+    pub fn register_dynamic(
+        &mut self,
+        descriptor: ComponentDescriptor,
+    ) -> Result<ComponentId, ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.register_dynamic(descriptor))
+    }
+
+    pub fn insert_by_id(
+        &mut self,
+        entity_id: EntityId,
+        component_id: ComponentId,
+        bytes: &[u8],
     ) -> Result<(), ArchetypeError> {
-        let archetype_arc = self.archetype.clone();
-        let mut archetype = archetype_arc.try_write().unwrap();
-        archetype.add_component_to_entity(entity_id, component)?;
+        self.write_archetypes(|archetypes| archetypes.insert_by_id(entity_id, component_id, bytes))
+    }
+
+    pub fn query_dynamic(
+        &mut self,
+        component_ids: &[ComponentId],
+    ) -> QueryFetched<DynamicItem<'_>> {
+        unsafe { self.read_archetypes().query_dynamic_unchecked(component_ids) }
+    }
+
+    // lets `clone_entity` copy components of this type
+    pub fn register_clone<T: Component + Clone>(&mut self) -> Result<(), ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.register_clone::<T>())?;
         Ok(())
     }
 
+    pub fn register_default<T: Component + Default>(&mut self) -> Result<(), ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.register_default::<T>())?;
+        Ok(())
+    }
+
+    pub fn register_debug<T: Component + std::fmt::Debug>(
+        &mut self,
+    ) -> Result<(), ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.register_debug::<T>())?;
+        Ok(())
+    }
+
+    // every component type the scene knows about, with its layout, storage and registered traits.
+    // registering needs the scene mutably, so no component is added while this is borrowed
+    pub fn components(&self) -> &Components {
+        self.read_archetypes().components()
+    }
+
+    // every entity getting a `T` without a `R` gets a default `R` too
+    pub fn register_required<T: Component, R: Component + Default>(
+        &mut self,
+    ) -> Result<(), ComponentError> {
+        self.register_required_with::<T, R>(R::default)
+    }
+
+    pub fn register_required_with<T: Component, R: Component>(
+        &mut self,
+        constructor: impl Fn() -> R + Send + Sync + 'static,
+    ) -> Result<(), ComponentError> {
+        self.write_archetypes(|archetypes| archetypes.register_required::<T, R>(constructor))
+    }
+
+    // panics when the requirements declared on `T` form a cycle, once the lock is released
+    fn set_hooks<T: Component>(&mut self, set: impl FnOnce(&mut ComponentHooks)) {
+        self.write_archetypes(|archetypes| {
+            let component_id = archetypes.components.register::<T>()?;
+            set(archetypes.components.hooks_mut(component_id));
+            Ok::<_, ComponentError>(())
+        })
+        .unwrap();
+    }
+
+    // runs when an entity gets a component of this type it didn't have before
+    pub fn on_add<T: Component>(
+        &mut self,
+        hook: impl Fn(&Scene, Entity) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.set_hooks::<T>(|hooks| {
+            hooks.on_add(hook);
+        });
+        self
+    }
+
+    // runs right after on_add, and again whenever the value is replaced
+    pub fn on_insert<T: Component>(
+        &mut self,
+        hook: impl Fn(&Scene, Entity) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.set_hooks::<T>(|hooks| {
+            hooks.on_insert(hook);
+        });
+        self
+    }
+
+    // runs when the component is removed or its entity despawned, the entity no longer
+    // has the component at that point so the removed value is passed along
+    pub fn on_remove<T: Component>(
+        &mut self,
+        hook: impl Fn(&Scene, Entity, &T) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.set_hooks::<T>(|hooks| {
+            hooks.on_remove(hook);
+        });
+        self
+    }
+
+    pub fn add_component_to_entity<T: Component>(
+        &mut self,
+        entity_id: EntityId,
+        component: T,
+    ) -> Result<(), ArchetypeError> {
+        self.write_archetypes(|archetypes| archetypes.add_component_to_entity(entity_id, component))
+    }
+
     pub fn add_system(
         &mut self,
         system: impl Fn(&Scene) + 'static,
@@ -163,7 +607,7 @@ impl Scene {
             .unwrap()
             .get_mut(&exec)
             .unwrap()
-            .push(Box::new(system));
+            .push((Box::new(system), Tick::default()));
         self
     }
 
@@ -174,7 +618,7 @@ impl Scene {
                 .unwrap()
                 .get_mut(&exec)
                 .unwrap()
-                .push(system);
+                .push((system, Tick::default()));
         }
         self
     }
@@ -189,7 +633,7 @@ impl Scene {
             .unwrap()
             .get_mut(&exec)
             .unwrap()
-            .push(Box::new(system));
+            .push((Box::new(system), Tick::default()));
         self
     }
 
@@ -200,57 +644,137 @@ impl Scene {
                 .unwrap()
                 .get_mut(&exec)
                 .unwrap()
-                .push(system);
+                .push((system, Tick::default()));
         }
         self
     }
 
+    // the only systems that get to the non-Send resources
+    pub fn add_main_thread_system(
+        &mut self,
+        system: impl FnMut(&mut Scene, &mut NonSendResources) + 'static,
+        exec: LifetimeSystemExec,
+    ) -> &mut Self {
+        self.systems_main_thread
+            .lock()
+            .unwrap()
+            .get_mut(&exec)
+            .unwrap()
+            .push((Box::new(system), Tick::default()));
+        self
+    }
+
+    pub fn add_main_thread_systems(&mut self, systems: Vec<MainThreadSystemFunc>) -> &mut Self {
+        for (system, exec) in systems {
+            self.systems_main_thread
+                .lock()
+                .unwrap()
+                .get_mut(&exec)
+                .unwrap()
+                .push((system, Tick::default()));
+        }
+        self
+    }
+
+    fn run_main_thread_systems(&mut self, exec: LifetimeSystemExec) {
+        let systems = self.systems_main_thread.clone();
+        let mut lock = systems.lock().unwrap();
+        let systems = lock.get_mut(&exec).unwrap();
+        // taken out of the scene for the run so the system can borrow both
+        let mut non_send = std::mem::take(&mut self.non_send);
+        for (system, last_run) in systems {
+            self.run_system(last_run, |scene| system(scene, &mut non_send));
+        }
+        self.non_send = non_send;
+    }
+
     pub fn run_system_on_begin(&mut self) {
         let systems = self.systems.clone();
-        let lock = systems.lock().unwrap();
-        let systems = lock.get(&LifetimeSystemExec::OnBegin).unwrap();
-        for system in systems {
-            system(self);
+        let mut lock = systems.lock().unwrap();
+        let systems = lock.get_mut(&LifetimeSystemExec::OnBegin).unwrap();
+        for (system, last_run) in systems {
+            self.run_system(last_run, |scene| system(scene));
         }
 
         let systems_mut = self.systems_mut.clone();
         let mut lock = systems_mut.lock().unwrap();
         let system = lock.get_mut(&LifetimeSystemExec::OnBegin).unwrap();
-        for system in system {
-            system(self);
+        for (system, last_run) in system {
+            self.run_system(last_run, system);
         }
+        self.run_main_thread_systems(LifetimeSystemExec::OnBegin);
     }
 
     pub fn run_system_on_update(&mut self) {
+        self.check_change_ticks();
         let systems = self.systems.clone();
-        let lock = systems.lock().unwrap();
-        let system = lock.get(&LifetimeSystemExec::OnUpdate).unwrap();
-        for system in system {
-            system(self);
+        let mut lock = systems.lock().unwrap();
+        let system = lock.get_mut(&LifetimeSystemExec::OnUpdate).unwrap();
+        for (system, last_run) in system {
+            self.run_system(last_run, |scene| system(scene));
         }
 
         let systems_mut = self.systems_mut.clone();
         let mut lock = systems_mut.lock().unwrap();
         let system = lock.get_mut(&LifetimeSystemExec::OnUpdate).unwrap();
-        for system in system {
-            system(self);
+        for (system, last_run) in system {
+            self.run_system(last_run, system);
         }
+        self.run_main_thread_systems(LifetimeSystemExec::OnUpdate);
+        // one run of the update systems is a frame
+        self.write_archetypes(|archetypes| archetypes.removed.update());
     }
 
     pub fn run_system_on_finish(&mut self) {
         let systems = self.systems.clone();
-        let lock = systems.lock().unwrap();
-        let systems = lock.get(&LifetimeSystemExec::OnFinish).unwrap();
-        for system in systems {
-            system(self);
+        let mut lock = systems.lock().unwrap();
+        let systems = lock.get_mut(&LifetimeSystemExec::OnFinish).unwrap();
+        for (system, last_run) in systems {
+            self.run_system(last_run, |scene| system(scene));
         }
 
         let systems_mut = self.systems_mut.clone();
         let mut lock = systems_mut.lock().unwrap();
         let system = lock.get_mut(&LifetimeSystemExec::OnFinish).unwrap();
-        for system in system {
-            system(self);
+        for (system, last_run) in system {
+            self.run_system(last_run, system);
         }
+        self.run_main_thread_systems(LifetimeSystemExec::OnFinish);
+    }
+
+    // keeps the stored ticks, last runs of the systems included, from being overtaken by the
+    // counter wrapping around. cheap unless enough ticks passed since the last check
+    fn check_change_ticks(&mut self) {
+        if !self.write_archetypes(|archetypes| archetypes.check_change_ticks()) {
+            return;
+        }
+        let this_run = self.read_archetypes().change_tick();
+        for systems in self.systems.lock().unwrap().values_mut() {
+            for (_, last_run) in systems {
+                last_run.check_tick(this_run);
+            }
+        }
+        for systems in self.systems_mut.lock().unwrap().values_mut() {
+            for (_, last_run) in systems {
+                last_run.check_tick(this_run);
+            }
+        }
+        for systems in self.systems_main_thread.lock().unwrap().values_mut() {
+            for (_, last_run) in systems {
+                last_run.check_tick(this_run);
+            }
+        }
+    }
+
+    // changes made by the system are stamped with the current tick, which becomes its last run,
+    // everything after it gets a newer tick
+    fn run_system(&mut self, last_run: &mut Tick, system: impl FnOnce(&mut Scene)) {
+        self.last_run = *last_run;
+        system(self);
+        self.flush();
+        *last_run = self.read_archetypes().change_tick();
+        self.last_run = Tick::default();
+        self.write_archetypes(|archetypes| archetypes.increment_change_tick());
     }
 
     pub fn run_systems(&mut self) {
@@ -263,8 +787,7 @@ impl Scene {
         self.is_running = true;
         self.run_system_on_begin();
         while self.is_running {
-            let time = self.query_single::<&Time>();
-            let delta_time = time.delta_time;
+            let delta_time = self.resource::<Time>().unwrap().delta_time;
             let r_timers = self.countdowns.clone();
             r_timers
                 .try_write()
@@ -272,7 +795,7 @@ impl Scene {
                 .update(delta_time.as_secs_f32(), self);
 
             self.run_system_on_update();
-            self.query_single::<&mut Time>().update();
+            self.resource_mut::<Time>().unwrap().update();
         }
         self.run_system_on_finish();
     }
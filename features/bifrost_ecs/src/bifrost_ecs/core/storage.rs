@@ -0,0 +1,469 @@
+use std::{
+    alloc::{self, Layout},
+    cell::UnsafeCell,
+    ptr::{self, NonNull},
+};
+
+use super::{
+    change_detection::{ComponentTicks, Tick},
+    component::ComponentInfo,
+    entity::Entity,
+};
+
+// type erased contiguous storage for one component type inside an archetype,
+// the component registry provides the layout and the drop function
+pub struct Column {
+    item_layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    data: NonNull<u8>,
+    capacity: usize,
+    len: usize,
+    // when each value was added and last changed, written through shared references like the values
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+}
+
+// components are required to be Send + Sync before they reach a column
+unsafe impl Send for Column {}
+unsafe impl Sync for Column {}
+
+impl Column {
+    pub fn new(info: &ComponentInfo) -> Self {
+        let item_layout = info.layout();
+        let capacity = if item_layout.size() == 0 { usize::MAX } else { 0 };
+        Self {
+            item_layout,
+            drop: info.drop(),
+            data: dangling(item_layout.align()),
+            capacity,
+            len: 0,
+            ticks: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_ptr(&self, row: usize) -> *mut u8 {
+        debug_assert!(row < self.len);
+        unsafe { self.data.as_ptr().add(row * self.item_layout.size()) }
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.data.as_ptr()
+    }
+
+    pub fn get_ticks(&self, row: usize) -> ComponentTicks {
+        unsafe { *self.ticks[row].get() }
+    }
+
+    pub(crate) fn ticks_ptr(&self) -> *mut ComponentTicks {
+        UnsafeCell::raw_get(self.ticks.as_ptr())
+    }
+
+    pub(crate) fn check_change_ticks(&mut self, this_run: Tick) {
+        for ticks in &mut self.ticks {
+            ticks.get_mut().check_ticks(this_run);
+        }
+    }
+
+    // moves the value behind `value` into the column, the caller must not drop it afterwards
+    pub(crate) unsafe fn push(&mut self, value: *mut u8, tick: Tick) {
+        self.reserve_one();
+        let size = self.item_layout.size();
+        ptr::copy_nonoverlapping(value, self.data.as_ptr().add(self.len * size), size);
+        self.push_ticks(ComponentTicks::new(tick));
+    }
+
+    // lets `write` initialize the new slot at the end of the column
+    pub(crate) unsafe fn push_with(&mut self, write: impl FnOnce(*mut u8), tick: Tick) {
+        self.reserve_one();
+        write(self.data.as_ptr().add(self.len * self.item_layout.size()));
+        self.push_ticks(ComponentTicks::new(tick));
+    }
+
+    // pushes a clone of the value at `row`, growing the column before the source is read
+    pub(crate) unsafe fn clone_row(
+        &mut self,
+        row: usize,
+        clone: unsafe fn(*const u8, *mut u8),
+        tick: Tick,
+    ) {
+        assert!(row < self.len);
+        self.reserve_one();
+        let size = self.item_layout.size();
+        clone(self.get_ptr(row), self.data.as_ptr().add(self.len * size));
+        self.push_ticks(ComponentTicks::new(tick));
+    }
+
+    fn push_ticks(&mut self, ticks: ComponentTicks) {
+        self.ticks.push(UnsafeCell::new(ticks));
+        self.len += 1;
+    }
+
+    pub fn swap_remove_and_drop(&mut self, row: usize) {
+        assert!(row < self.len);
+        unsafe {
+            let removed = self.get_ptr(row);
+            if let Some(drop) = self.drop {
+                drop(removed);
+            }
+            self.fill_hole(row);
+        }
+    }
+
+    // moves the value at `row` to the end of `other` along with its ticks,
+    // both columns must store the same type
+    pub(crate) unsafe fn swap_remove_into(&mut self, row: usize, other: &mut Column) {
+        assert!(row < self.len);
+        debug_assert_eq!(self.item_layout, other.item_layout);
+        let ticks = self.get_ticks(row);
+        other.push(self.get_ptr(row), ticks.added);
+        *other.ticks[other.len - 1].get_mut() = ticks;
+        self.fill_hole(row);
+    }
+
+    // the value at `row` was moved out by the caller, only its slot is released
+    pub(crate) unsafe fn swap_remove_forget(&mut self, row: usize) {
+        assert!(row < self.len);
+        self.fill_hole(row);
+    }
+
+    // moves the last value into the hole left at `row`
+    unsafe fn fill_hole(&mut self, row: usize) {
+        let last = self.len - 1;
+        if row != last {
+            let size = self.item_layout.size();
+            ptr::copy_nonoverlapping(self.get_ptr(last), self.get_ptr(row), size);
+        }
+        self.ticks.swap_remove(row);
+        self.len -= 1;
+    }
+
+    fn reserve_one(&mut self) {
+        if self.len < self.capacity {
+            return;
+        }
+        let new_capacity = if self.capacity == 0 { 4 } else { self.capacity * 2 };
+        let new_layout = array_layout(self.item_layout, new_capacity);
+        let data = unsafe {
+            if self.capacity == 0 {
+                alloc::alloc(new_layout)
+            } else {
+                alloc::realloc(
+                    self.data.as_ptr(),
+                    array_layout(self.item_layout, self.capacity),
+                    new_layout.size(),
+                )
+            }
+        };
+        self.data = NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(new_layout));
+        self.capacity = new_capacity;
+    }
+}
+
+impl Drop for Column {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            for row in 0..self.len {
+                unsafe { drop(self.get_ptr(row)) };
+            }
+        }
+        if self.item_layout.size() != 0 && self.capacity != 0 {
+            unsafe {
+                alloc::dealloc(
+                    self.data.as_ptr(),
+                    array_layout(self.item_layout, self.capacity),
+                )
+            };
+        }
+    }
+}
+
+// storage for components that come and go often, the values stay packed in `dense`
+// and `sparse` maps the entity index to the slot holding its value
+pub struct SparseSet {
+    dense: Column,
+    entities: Vec<Entity>,
+    sparse: Vec<Option<usize>>,
+}
+
+impl SparseSet {
+    pub fn new(info: &ComponentInfo) -> Self {
+        Self {
+            dense: Column::new(info),
+            entities: Vec::new(),
+            sparse: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let index = (*self.sparse.get(entity.index() as usize)?)?;
+        (self.entities[index] == entity).then_some(index)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub fn get_ptr(&self, entity: Entity) -> Option<*mut u8> {
+        self.dense_index(entity)
+            .map(|index| self.dense.get_ptr(index))
+    }
+
+    pub fn get_ticks(&self, entity: Entity) -> Option<ComponentTicks> {
+        self.dense_index(entity)
+            .map(|index| self.dense.get_ticks(index))
+    }
+
+    pub(crate) fn get_ticks_ptr(&self, entity: Entity) -> Option<*mut ComponentTicks> {
+        self.dense_index(entity)
+            .map(|index| unsafe { self.dense.ticks_ptr().add(index) })
+    }
+
+    pub(crate) fn check_change_ticks(&mut self, this_run: Tick) {
+        self.dense.check_change_ticks(this_run);
+    }
+
+    // moves the value behind `value` into the set, the entity must not have one yet
+    pub(crate) unsafe fn insert(&mut self, entity: Entity, value: *mut u8, tick: Tick) {
+        self.dense.push(value, tick);
+        self.link(entity);
+    }
+
+    // lets `write` initialize the value of the entity, which must not have one yet
+    pub(crate) unsafe fn insert_with(
+        &mut self,
+        entity: Entity,
+        write: impl FnOnce(*mut u8),
+        tick: Tick,
+    ) {
+        self.dense.push_with(write, tick);
+        self.link(entity);
+    }
+
+    // gives `to` a clone of the value of `from`, `to` must not have one yet
+    pub(crate) unsafe fn clone_value(
+        &mut self,
+        from: Entity,
+        to: Entity,
+        clone: unsafe fn(*const u8, *mut u8),
+        tick: Tick,
+    ) {
+        let Some(index) = self.dense_index(from) else {
+            return;
+        };
+        self.dense.clone_row(index, clone, tick);
+        self.link(to);
+    }
+
+    // points the entity at the value just pushed to `dense`
+    fn link(&mut self, entity: Entity) {
+        debug_assert!(!self.contains(entity));
+        let sparse_index = entity.index() as usize;
+        if sparse_index >= self.sparse.len() {
+            self.sparse.resize(sparse_index + 1, None);
+        }
+        self.sparse[sparse_index] = Some(self.entities.len());
+        self.entities.push(entity);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let Some(index) = self.dense_index(entity) else {
+            return false;
+        };
+        self.dense.swap_remove_and_drop(index);
+        self.unlink(entity, index);
+        true
+    }
+
+    // moves the value of the entity to the end of `other` instead of dropping it
+    pub(crate) fn remove_into(&mut self, entity: Entity, other: &mut Column) -> bool {
+        let Some(index) = self.dense_index(entity) else {
+            return false;
+        };
+        unsafe { self.dense.swap_remove_into(index, other) };
+        self.unlink(entity, index);
+        true
+    }
+
+    fn unlink(&mut self, entity: Entity, index: usize) {
+        self.entities.swap_remove(index);
+        self.sparse[entity.index() as usize] = None;
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.index() as usize] = Some(index);
+        }
+    }
+}
+
+fn array_layout(item_layout: Layout, capacity: usize) -> Layout {
+    item_layout
+        .size()
+        .checked_mul(capacity)
+        .and_then(|size| Layout::from_size_align(size, item_layout.align()).ok())
+        .expect("component column capacity overflow")
+}
+
+fn dangling(align: usize) -> NonNull<u8> {
+    NonNull::new(align as *mut u8).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem::ManuallyDrop,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+    use crate::core::{
+        component::{Component, Components},
+        test_utils::Tracked,
+    };
+
+    static UNIT_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Unit;
+
+    impl Drop for Unit {
+        fn drop(&mut self) {
+            UNIT_DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(not(feature = "blanket_component"))]
+    impl Component for Unit {}
+
+    fn column<T: Component>() -> Column {
+        let mut components = Components::new();
+        let id = components.register::<T>().unwrap();
+        Column::new(components.get_info(id).unwrap())
+    }
+
+    fn sparse_set<T: Component>() -> SparseSet {
+        let mut components = Components::new();
+        let id = components.register::<T>().unwrap();
+        SparseSet::new(components.get_info(id).unwrap())
+    }
+
+    fn push<T>(column: &mut Column, value: T, tick: u32) {
+        let mut value = ManuallyDrop::new(value);
+        unsafe { column.push(&mut *value as *mut T as *mut u8, Tick::new(tick)) };
+    }
+
+    fn insert<T>(sparse_set: &mut SparseSet, entity: Entity, value: T) {
+        let mut value = ManuallyDrop::new(value);
+        unsafe { sparse_set.insert(entity, &mut *value as *mut T as *mut u8, Tick::new(0)) };
+    }
+
+    fn values(column: &Column) -> Vec<u32> {
+        (0..column.len())
+            .map(|row| unsafe { (*(column.get_ptr(row) as *const Tracked)).1 })
+            .collect()
+    }
+
+    #[test]
+    fn push_grows_and_keeps_values() {
+        let counter = Arc::new(());
+        let mut column = column::<Tracked>();
+        for value in 0..10 {
+            push(&mut column, Tracked(counter.clone(), value), value);
+        }
+        assert_eq!(values(&column), (0..10).collect::<Vec<_>>());
+        assert_eq!(column.get_ticks(7).added, Tick::new(7));
+        drop(column);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn swap_remove_drops_once_and_fills_the_hole() {
+        let counter = Arc::new(());
+        let mut column = column::<Tracked>();
+        for value in 0..3 {
+            push(&mut column, Tracked(counter.clone(), value), value);
+        }
+        column.swap_remove_and_drop(0);
+        assert_eq!(Arc::strong_count(&counter), 3);
+        assert_eq!(values(&column), [2, 1]);
+        assert_eq!(column.get_ticks(0).added, Tick::new(2));
+        column.swap_remove_and_drop(1);
+        assert_eq!(values(&column), [2]);
+        drop(column);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn swap_remove_into_moves_the_value_and_its_ticks() {
+        let counter = Arc::new(());
+        let mut from = column::<Tracked>();
+        let mut to = column::<Tracked>();
+        push(&mut from, Tracked(counter.clone(), 1), 1);
+        push(&mut from, Tracked(counter.clone(), 2), 2);
+        push(&mut to, Tracked(counter.clone(), 3), 3);
+        unsafe { from.swap_remove_into(0, &mut to) };
+        assert_eq!(Arc::strong_count(&counter), 4);
+        assert_eq!(values(&from), [2]);
+        assert_eq!(values(&to), [3, 1]);
+        assert_eq!(to.get_ticks(1).added, Tick::new(1));
+        drop(from);
+        drop(to);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn zero_sized_values_are_dropped() {
+        let mut column = column::<Unit>();
+        for _ in 0..3 {
+            push(&mut column, Unit, 0);
+        }
+        column.swap_remove_and_drop(1);
+        assert_eq!(column.len(), 2);
+        assert_eq!(UNIT_DROPS.load(Ordering::SeqCst), 1);
+        drop(column);
+        assert_eq!(UNIT_DROPS.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn sparse_set_relinks_the_swapped_entity() {
+        let counter = Arc::new(());
+        let mut sparse_set = sparse_set::<Tracked>();
+        let first = Entity::new(0, 0);
+        let second = Entity::new(5, 0);
+        insert(&mut sparse_set, first, Tracked(counter.clone(), 1));
+        insert(&mut sparse_set, second, Tracked(counter.clone(), 2));
+        assert!(!sparse_set.contains(Entity::new(5, 1)));
+        assert!(sparse_set.remove(first));
+        assert!(!sparse_set.remove(first));
+        assert_eq!(Arc::strong_count(&counter), 2);
+        let value = sparse_set.get_ptr(second).unwrap() as *const Tracked;
+        assert_eq!(unsafe { (*value).1 }, 2);
+
+        let mut to = column::<Tracked>();
+        assert!(sparse_set.remove_into(second, &mut to));
+        assert!(sparse_set.is_empty());
+        assert_eq!(values(&to), [2]);
+        drop(sparse_set);
+        assert_eq!(Arc::strong_count(&counter), 2);
+        drop(to);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+}
@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+#[cfg(not(feature = "blanket_component"))]
+use super::component::Component;
+
+// every live value holds a clone of the `Arc`, its strong count tells how many weren't dropped
+pub(crate) struct Tracked(#[allow(dead_code)] pub Arc<()>, pub u32);
+
+pub(crate) struct Marker;
+
+#[cfg(not(feature = "blanket_component"))]
+impl Component for Tracked {}
+#[cfg(not(feature = "blanket_component"))]
+impl Component for Marker {}
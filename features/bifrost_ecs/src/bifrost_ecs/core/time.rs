@@ -7,6 +7,12 @@ pub struct Time {
     pub time: f32,
 }
 
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Time {
     pub fn new() -> Self {
         Self {
@@ -17,6 +17,12 @@ pub trait AssetLoader {
 pub struct Asset<T: AssetLoader> {
     loaded_data: HashMap<String, Arc<T::Asset>>,
 }
+impl<T: AssetLoader> Default for Asset<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: AssetLoader> Asset<T> {
     pub fn new() -> Self {
         Self {
@@ -25,14 +31,14 @@ impl<T: AssetLoader> Asset<T> {
     }
 
     pub fn load(&mut self, scene: &Scene, path: &str) -> Result<Arc<T::Asset>, AssetLoaderError> {
-        if let Some(_) = self.loaded_data.get(path) {
+        if self.loaded_data.contains_key(path) {
             return Err(AssetLoaderError::new_already_loaded(path));
         }
 
         let data = T::load(scene, path)?;
-        let filename = path.to_string().split("/").last().unwrap().to_string();
+        let filename = path.split('/').next_back().unwrap().to_string();
         // remove extension
-        let filename = filename.split(".").next().unwrap().to_string();
+        let filename = filename.split('.').next().unwrap().to_string();
         self.loaded_data.insert(filename.clone(), data);
         Ok(self.loaded_data.get(&filename).unwrap().clone())
     }
@@ -46,7 +52,7 @@ impl<T: AssetLoader> Asset<T> {
     }
 
     pub fn remove(&mut self, path: &str) -> Result<(), AssetLoaderError> {
-        if let Some(_) = self.loaded_data.get(path) {
+        if self.loaded_data.contains_key(path) {
             self.loaded_data.remove(path);
             Ok(())
         } else {